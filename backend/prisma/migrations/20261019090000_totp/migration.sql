@@ -0,0 +1,20 @@
+-- AlterTable
+ALTER TABLE "User" ADD COLUMN     "totp_enabled" BOOLEAN NOT NULL DEFAULT false,
+ADD COLUMN     "totp_last_step" BIGINT,
+ADD COLUMN     "totp_secret" TEXT;
+
+-- CreateTable
+CREATE TABLE "RecoveryCode" (
+    "id" BIGSERIAL NOT NULL,
+    "user_id" UUID NOT NULL,
+    "hash" TEXT NOT NULL,
+    "used_at" TIMESTAMP(3),
+
+    CONSTRAINT "RecoveryCode_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "RecoveryCode_user_id_idx" ON "RecoveryCode"("user_id");
+
+-- AddForeignKey
+ALTER TABLE "RecoveryCode" ADD CONSTRAINT "RecoveryCode_user_id_fkey" FOREIGN KEY ("user_id") REFERENCES "User"("id") ON DELETE CASCADE ON UPDATE CASCADE;
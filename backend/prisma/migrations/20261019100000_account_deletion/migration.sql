@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "User" ADD COLUMN     "deletion_scheduled_at" TIMESTAMP(3);
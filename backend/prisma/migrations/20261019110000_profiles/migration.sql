@@ -0,0 +1,9 @@
+-- AlterTable
+ALTER TABLE "User" ADD COLUMN     "avatar_url" TEXT,
+ADD COLUMN     "bio" TEXT,
+ADD COLUMN     "display_name" TEXT,
+ADD COLUMN     "email_token_expires" TIMESTAMP(3),
+ADD COLUMN     "email_token_hash" TEXT,
+ADD COLUMN     "pending_email" TEXT,
+ADD COLUMN     "username_changed_at" TIMESTAMP(3),
+ADD COLUMN     "website" TEXT;
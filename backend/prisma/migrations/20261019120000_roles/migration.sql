@@ -0,0 +1,35 @@
+-- CreateEnum
+CREATE TYPE "Role" AS ENUM ('User', 'Moderator', 'Admin');
+
+-- CreateEnum
+CREATE TYPE "ModerationKind" AS ENUM ('DeletePost', 'HidePost', 'UnhidePost', 'DeleteComment', 'HideComment', 'UnhideComment', 'ChangeRole');
+
+-- AlterTable
+ALTER TABLE "BlogPost" ADD COLUMN     "hidden_at" TIMESTAMP(3);
+
+-- AlterTable
+ALTER TABLE "Comment" ADD COLUMN     "hidden_at" TIMESTAMP(3);
+
+-- AlterTable
+ALTER TABLE "User" ADD COLUMN     "role" "Role" NOT NULL DEFAULT 'User';
+
+-- CreateTable
+CREATE TABLE "ModerationAction" (
+    "id" BIGSERIAL NOT NULL,
+    "moderator_id" UUID,
+    "kind" "ModerationKind" NOT NULL,
+    "target_id" UUID NOT NULL,
+    "reason" TEXT,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "ModerationAction_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "ModerationAction_created_at_idx" ON "ModerationAction"("created_at");
+
+-- CreateIndex
+CREATE INDEX "ModerationAction_target_id_idx" ON "ModerationAction"("target_id");
+
+-- AddForeignKey
+ALTER TABLE "ModerationAction" ADD CONSTRAINT "ModerationAction_moderator_id_fkey" FOREIGN KEY ("moderator_id") REFERENCES "User"("id") ON DELETE SET NULL ON UPDATE CASCADE;
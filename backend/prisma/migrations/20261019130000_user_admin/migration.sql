@@ -0,0 +1,17 @@
+-- AlterEnum
+-- This migration adds more than one value to an enum.
+-- With PostgreSQL versions 11 and earlier, this is not possible
+-- in a single migration. This can be worked around by creating
+-- multiple migrations, each migration adding only one value to
+-- the enum.
+
+
+ALTER TYPE "ModerationKind" ADD VALUE 'SuspendUser';
+ALTER TYPE "ModerationKind" ADD VALUE 'UnsuspendUser';
+ALTER TYPE "ModerationKind" ADD VALUE 'ForceLogout';
+ALTER TYPE "ModerationKind" ADD VALUE 'ResetPassword';
+
+-- AlterTable
+ALTER TABLE "User" ADD COLUMN     "sessions_valid_after" TIMESTAMP(3),
+ADD COLUMN     "suspended_at" TIMESTAMP(3),
+ADD COLUMN     "suspension_reason" TEXT;
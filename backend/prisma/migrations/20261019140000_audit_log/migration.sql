@@ -0,0 +1,35 @@
+-- CreateEnum
+CREATE TYPE "AuditKind" AS ENUM ('LoginSuccess', 'LoginFailure', 'Signup', 'PasswordChange', 'PostDelete', 'CommentDelete', 'RoleChange', 'TokenCreate');
+
+-- CreateTable
+CREATE TABLE "AuditEvent" (
+    "id" BIGSERIAL NOT NULL,
+    "kind" "AuditKind" NOT NULL,
+    "actor_id" UUID,
+    "target_id" UUID,
+    "ip" TEXT,
+    "detail" TEXT,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "AuditEvent_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "AuditEvent_created_at_idx" ON "AuditEvent"("created_at");
+
+-- CreateIndex
+CREATE INDEX "AuditEvent_actor_id_idx" ON "AuditEvent"("actor_id");
+
+-- CreateIndex
+CREATE INDEX "AuditEvent_target_id_idx" ON "AuditEvent"("target_id");
+
+-- Enforce append-only, the application never updates or deletes audit events
+CREATE FUNCTION "AuditEvent_append_only"() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'AuditEvent is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "AuditEvent_append_only"
+    BEFORE UPDATE OR DELETE ON "AuditEvent"
+    FOR EACH ROW EXECUTE FUNCTION "AuditEvent_append_only"();
@@ -0,0 +1,39 @@
+-- CreateEnum
+CREATE TYPE "ReportResolution" AS ENUM ('Dismissed', 'ContentHidden', 'AuthorSuspended');
+
+-- CreateTable
+CREATE TABLE "Report" (
+    "id" BIGSERIAL NOT NULL,
+    "reporter_id" UUID,
+    "post_id" UUID,
+    "comment_id" UUID,
+    "reason" TEXT NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "resolved_at" TIMESTAMP(3),
+    "resolved_by_id" UUID,
+    "resolution" "ReportResolution",
+
+    CONSTRAINT "Report_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "Report_single_target" CHECK (("post_id" IS NULL) <> ("comment_id" IS NULL))
+);
+
+-- CreateIndex
+CREATE INDEX "Report_resolved_at_created_at_idx" ON "Report"("resolved_at", "created_at");
+
+-- CreateIndex
+CREATE INDEX "Report_post_id_idx" ON "Report"("post_id");
+
+-- CreateIndex
+CREATE INDEX "Report_comment_id_idx" ON "Report"("comment_id");
+
+-- AddForeignKey
+ALTER TABLE "Report" ADD CONSTRAINT "Report_reporter_id_fkey" FOREIGN KEY ("reporter_id") REFERENCES "User"("id") ON DELETE SET NULL ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "Report" ADD CONSTRAINT "Report_post_id_fkey" FOREIGN KEY ("post_id") REFERENCES "BlogPost"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "Report" ADD CONSTRAINT "Report_comment_id_fkey" FOREIGN KEY ("comment_id") REFERENCES "Comment"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "Report" ADD CONSTRAINT "Report_resolved_by_id_fkey" FOREIGN KEY ("resolved_by_id") REFERENCES "User"("id") ON DELETE SET NULL ON UPDATE CASCADE;
@@ -0,0 +1,24 @@
+-- AlterTable
+ALTER TABLE "User" ADD COLUMN     "invite_code" TEXT;
+
+-- CreateTable
+CREATE TABLE "Invite" (
+    "code" TEXT NOT NULL,
+    "creator_id" UUID,
+    "max_uses" INTEGER,
+    "uses" INTEGER NOT NULL DEFAULT 0,
+    "expires_at" TIMESTAMP(3),
+    "revoked_at" TIMESTAMP(3),
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "Invite_pkey" PRIMARY KEY ("code")
+);
+
+-- CreateIndex
+CREATE INDEX "Invite_creator_id_idx" ON "Invite"("creator_id");
+
+-- AddForeignKey
+ALTER TABLE "User" ADD CONSTRAINT "User_invite_code_fkey" FOREIGN KEY ("invite_code") REFERENCES "Invite"("code") ON DELETE SET NULL ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "Invite" ADD CONSTRAINT "Invite_creator_id_fkey" FOREIGN KEY ("creator_id") REFERENCES "User"("id") ON DELETE SET NULL ON UPDATE CASCADE;
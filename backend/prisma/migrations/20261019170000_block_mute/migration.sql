@@ -0,0 +1,35 @@
+-- CreateTable
+CREATE TABLE "_UserBlock" (
+    "A" UUID NOT NULL,
+    "B" UUID NOT NULL
+);
+
+-- CreateTable
+CREATE TABLE "_UserMute" (
+    "A" UUID NOT NULL,
+    "B" UUID NOT NULL
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "_UserBlock_AB_unique" ON "_UserBlock"("A", "B");
+
+-- CreateIndex
+CREATE INDEX "_UserBlock_B_index" ON "_UserBlock"("B");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "_UserMute_AB_unique" ON "_UserMute"("A", "B");
+
+-- CreateIndex
+CREATE INDEX "_UserMute_B_index" ON "_UserMute"("B");
+
+-- AddForeignKey
+ALTER TABLE "_UserBlock" ADD CONSTRAINT "_UserBlock_A_fkey" FOREIGN KEY ("A") REFERENCES "User"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "_UserBlock" ADD CONSTRAINT "_UserBlock_B_fkey" FOREIGN KEY ("B") REFERENCES "User"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "_UserMute" ADD CONSTRAINT "_UserMute_A_fkey" FOREIGN KEY ("A") REFERENCES "User"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "_UserMute" ADD CONSTRAINT "_UserMute_B_fkey" FOREIGN KEY ("B") REFERENCES "User"("id") ON DELETE CASCADE ON UPDATE CASCADE;
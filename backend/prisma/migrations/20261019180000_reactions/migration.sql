@@ -0,0 +1,33 @@
+-- CreateTable
+CREATE TABLE "Reaction" (
+    "id" BIGSERIAL NOT NULL,
+    "user_id" UUID NOT NULL,
+    "emoji" TEXT NOT NULL,
+    "post_id" UUID,
+    "comment_id" UUID,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "Reaction_pkey" PRIMARY KEY ("id"),
+    CONSTRAINT "Reaction_single_target" CHECK (("post_id" IS NULL) <> ("comment_id" IS NULL))
+);
+
+-- CreateIndex
+CREATE INDEX "Reaction_post_id_idx" ON "Reaction"("post_id");
+
+-- CreateIndex
+CREATE INDEX "Reaction_comment_id_idx" ON "Reaction"("comment_id");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "Reaction_user_id_post_id_emoji_key" ON "Reaction"("user_id", "post_id", "emoji");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "Reaction_user_id_comment_id_emoji_key" ON "Reaction"("user_id", "comment_id", "emoji");
+
+-- AddForeignKey
+ALTER TABLE "Reaction" ADD CONSTRAINT "Reaction_user_id_fkey" FOREIGN KEY ("user_id") REFERENCES "User"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "Reaction" ADD CONSTRAINT "Reaction_post_id_fkey" FOREIGN KEY ("post_id") REFERENCES "BlogPost"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "Reaction" ADD CONSTRAINT "Reaction_comment_id_fkey" FOREIGN KEY ("comment_id") REFERENCES "Comment"("id") ON DELETE CASCADE ON UPDATE CASCADE;
@@ -0,0 +1,19 @@
+-- CreateTable
+CREATE TABLE "PostView" (
+    "id" BIGSERIAL NOT NULL,
+    "post_id" UUID NOT NULL,
+    "day" DATE NOT NULL,
+    "visitor" TEXT NOT NULL,
+    "referrer" TEXT,
+
+    CONSTRAINT "PostView_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "PostView_post_id_day_idx" ON "PostView"("post_id", "day");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "PostView_post_id_day_visitor_key" ON "PostView"("post_id", "day", "visitor");
+
+-- AddForeignKey
+ALTER TABLE "PostView" ADD CONSTRAINT "PostView_post_id_fkey" FOREIGN KEY ("post_id") REFERENCES "BlogPost"("id") ON DELETE CASCADE ON UPDATE CASCADE;
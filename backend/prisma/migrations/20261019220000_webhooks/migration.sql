@@ -0,0 +1,41 @@
+-- CreateEnum
+CREATE TYPE "WebhookEvent" AS ENUM ('PostCreated', 'PostUpdated', 'CommentCreated', 'Ping');
+
+-- CreateTable
+CREATE TABLE "Webhook" (
+    "id" UUID NOT NULL,
+    "owner_id" UUID,
+    "url" TEXT NOT NULL,
+    "secret" TEXT NOT NULL,
+    "events" "WebhookEvent"[],
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "Webhook_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateTable
+CREATE TABLE "WebhookDelivery" (
+    "id" BIGSERIAL NOT NULL,
+    "webhook_id" UUID NOT NULL,
+    "event" "WebhookEvent" NOT NULL,
+    "payload" TEXT NOT NULL,
+    "attempts" INTEGER NOT NULL DEFAULT 0,
+    "status_code" INTEGER,
+    "error" TEXT,
+    "delivered_at" TIMESTAMP(3),
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "WebhookDelivery_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "Webhook_owner_id_idx" ON "Webhook"("owner_id");
+
+-- CreateIndex
+CREATE INDEX "WebhookDelivery_webhook_id_created_at_idx" ON "WebhookDelivery"("webhook_id", "created_at");
+
+-- AddForeignKey
+ALTER TABLE "Webhook" ADD CONSTRAINT "Webhook_owner_id_fkey" FOREIGN KEY ("owner_id") REFERENCES "User"("id") ON DELETE CASCADE ON UPDATE CASCADE;
+
+-- AddForeignKey
+ALTER TABLE "WebhookDelivery" ADD CONSTRAINT "WebhookDelivery_webhook_id_fkey" FOREIGN KEY ("webhook_id") REFERENCES "Webhook"("id") ON DELETE CASCADE ON UPDATE CASCADE;
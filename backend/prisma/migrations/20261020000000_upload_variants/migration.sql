@@ -0,0 +1,29 @@
+-- CreateEnum
+CREATE TYPE "UploadVariantKind" AS ENUM ('Thumbnail', 'Medium');
+
+-- AlterTable
+ALTER TABLE "Upload" ADD COLUMN     "height" INTEGER,
+ADD COLUMN     "width" INTEGER;
+
+-- CreateTable
+CREATE TABLE "UploadVariant" (
+    "id" BIGSERIAL NOT NULL,
+    "upload_id" UUID NOT NULL,
+    "kind" "UploadVariantKind" NOT NULL,
+    "key" TEXT NOT NULL,
+    "content_type" TEXT NOT NULL,
+    "size" INTEGER NOT NULL,
+    "width" INTEGER NOT NULL,
+    "height" INTEGER NOT NULL,
+
+    CONSTRAINT "UploadVariant_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "UploadVariant_key_key" ON "UploadVariant"("key");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "UploadVariant_upload_id_kind_key" ON "UploadVariant"("upload_id", "kind");
+
+-- AddForeignKey
+ALTER TABLE "UploadVariant" ADD CONSTRAINT "UploadVariant_upload_id_fkey" FOREIGN KEY ("upload_id") REFERENCES "Upload"("id") ON DELETE CASCADE ON UPDATE CASCADE;
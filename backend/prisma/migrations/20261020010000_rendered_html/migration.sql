@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "BlogPostVersion" ADD COLUMN     "html" TEXT,
+ADD COLUMN     "html_renderer" INTEGER;
@@ -0,0 +1,6 @@
+-- AlterTable
+ALTER TABLE "BlogPostVersion" ADD COLUMN     "canonical_url" TEXT,
+ADD COLUMN     "cover_image" TEXT,
+ADD COLUMN     "language" TEXT,
+ADD COLUMN     "summary" TEXT,
+ADD COLUMN     "tags" TEXT[] DEFAULT ARRAY[]::TEXT[];
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "BlogPostVersion" ADD COLUMN     "excerpt" TEXT,
+ADD COLUMN     "excerpt_length" INTEGER,
+ADD COLUMN     "excerpt_renderer" INTEGER,
+ADD COLUMN     "word_count" INTEGER;
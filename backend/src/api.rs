@@ -1,7 +1,12 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, net::IpAddr};
 
-use crate::{auth, bounded::BoundString, BlogDrownState};
-use chrono::{DateTime, FixedOffset};
+use crate::{
+    auth,
+    bounded::BoundString,
+    prisma::{AuditKind, ModerationKind, NotificationKind, Role, UploadVariantKind, WebhookEvent},
+    BlogDrownState,
+};
+use chrono::{DateTime, FixedOffset, NaiveDate};
 use prisma_client_rust::{
     prisma_errors::query_engine::{RecordNotFound, UniqueKeyViolation},
     QueryError,
@@ -17,10 +22,61 @@ use axum::{
     Json, RequestExt, Router,
 };
 use ulid::Ulid;
+use uuid::Uuid;
+
+/// Builds a [`UserProfile`] from any user selection containing the profile fields
+macro_rules! user_profile {
+    ($user:expr) => {{
+        let user = $user;
+
+        $crate::api::UserProfile {
+            min: $crate::api::MinUser {
+                id: $crate::api::expect_uuid(&user.id),
+                username: $crate::bounded::BoundString::new_unchecked(user.username),
+            },
+            profile: $crate::api::Profile {
+                display_name: user
+                    .display_name
+                    .map($crate::bounded::BoundString::new_unchecked),
+                bio: user.bio.map($crate::bounded::BoundString::new_unchecked),
+                avatar_url: user
+                    .avatar_url
+                    .map($crate::bounded::BoundString::new_unchecked),
+                website: user
+                    .website
+                    .map($crate::bounded::BoundString::new_unchecked),
+            },
+            created_at: user.created_at,
+        }
+    }};
+}
 
+mod account;
+mod admin;
+mod analytics;
 mod blog;
 mod comments;
 mod follows;
+mod highlight;
+mod invites;
+mod mentions;
+mod moderation;
+mod notifications;
+mod reactions;
+mod reports;
+mod socket;
+mod uploads;
+mod users;
+mod webhooks;
+
+pub use account::purge_scheduled_deletions;
+pub use notifications::notification_item;
+pub use uploads::{media_routes, remove_stored};
+pub use webhooks::{dispatch_webhooks, prune_deliveries};
+
+fn expect_uuid(s: &str) -> Ulid {
+    Ulid::from(s.parse::<Uuid>().expect("Database stores uuid"))
+}
 
 #[derive(Serialize, Default)]
 pub struct Error {
@@ -150,25 +206,99 @@ pub struct MinUser {
     pub username: Username,
 }
 
+type DisplayName = BoundString<1, 64>;
+type Bio = BoundString<1, 2000>;
+type ProfileUrl = BoundString<8, 2048>;
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct Profile {
+    pub display_name: Option<DisplayName>,
+    pub bio: Option<Bio>,
+    pub avatar_url: Option<ProfileUrl>,
+    pub website: Option<ProfileUrl>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct AuthUser {
     #[serde(flatten)]
     pub min: MinUser,
+    #[serde(flatten)]
+    pub profile: Profile,
     pub email: Email,
+    /// Email address awaiting verification before it replaces `email`
+    pub pending_email: Option<Email>,
     pub created_at: DateTime<FixedOffset>,
+    pub role: Role,
+    pub totp_enabled: bool,
 }
 
+#[derive(Serialize)]
+pub struct UserProfile {
+    #[serde(flatten)]
+    pub min: MinUser,
+    #[serde(flatten)]
+    pub profile: Profile,
+    pub created_at: DateTime<FixedOffset>,
+}
+
+type TotpCode = BoundString<6, 6>;
+type RecoveryCode = BoundString<10, 16>;
+
 #[derive(Deserialize)]
 pub struct Login {
     pub email: Email,
     pub password: SecretString,
+    pub totp: Option<TotpCode>,
+    pub recovery_code: Option<RecoveryCode>,
 }
 
+type InviteCode = BoundString<8, 64>;
+
 #[derive(Deserialize)]
 pub struct Signup {
     pub email: Email,
     pub username: Username,
     pub password: SecretString,
+    /// Required when registration is invite-only
+    pub invite: Option<InviteCode>,
+}
+
+#[derive(Serialize)]
+pub struct RegistrationInfo {
+    pub mode: auth::Registration,
+}
+
+#[derive(Serialize)]
+pub struct CsrfToken {
+    pub token: String,
+}
+
+#[derive(Serialize)]
+pub struct SessionToken {
+    pub token: String,
+}
+
+#[derive(Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct TotpConfirm {
+    pub code: TotpCode,
+}
+
+#[derive(Deserialize)]
+pub struct TotpDisable {
+    pub password: SecretString,
+    pub totp: Option<TotpCode>,
+    pub recovery_code: Option<RecoveryCode>,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
 }
 
 // Posts / Comments
@@ -198,6 +328,77 @@ pub struct NewBlogPostRes {
 #[derive(Deserialize, Debug)]
 pub struct GetPost {
     id: Ulid,
+    /// Also render the body to html
+    #[serde(default)]
+    html: bool,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PostSort {
+    #[default]
+    New,
+    /// Most reactions first
+    Popular,
+}
+
+#[derive(Deserialize)]
+pub struct GetAllPosts {
+    #[serde(default)]
+    sort: PostSort,
+}
+
+#[derive(Deserialize)]
+pub struct AnalyticsQuery {
+    /// Number of days to report, ending today
+    days: Option<u16>,
+}
+
+#[derive(Serialize)]
+pub struct DailyAnalytics {
+    day: NaiveDate,
+    /// Deduplicated per visitor
+    views: i64,
+    comments: i64,
+    reactions: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReferrerCount {
+    /// Unset for direct visits
+    referrer: Option<String>,
+    views: i64,
+}
+
+#[derive(Serialize)]
+pub struct PostAnalytics {
+    views: i64,
+    unique_visitors: i64,
+    daily: Vec<DailyAnalytics>,
+    referrers: Vec<ReferrerCount>,
+}
+
+#[derive(Deserialize)]
+pub struct React {
+    emoji: BoundString<1, 32>,
+}
+
+#[derive(Serialize)]
+pub struct ReactionCount {
+    emoji: String,
+    count: i64,
+    /// Whether the current user reacted with this emoji
+    reacted: bool,
+}
+
+/// Set by the front matter of the latest version
+#[derive(Serialize)]
+pub struct PostMeta {
+    summary: Option<String>,
+    cover_image: Option<String>,
+    tags: Vec<String>,
+    canonical_url: Option<String>,
+    language: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -206,8 +407,14 @@ pub struct GetAllPostsItem {
     id_ts: IdAndTimestamps,
     title_norm: String,
     title: String,
+    /// The author's summary, or the start of the body as plain text cut at a sentence or word
     partial_body: String,
+    word_count: usize,
+    reading_time_minutes: usize,
+    #[serde(flatten)]
+    meta: PostMeta,
     user: MinUser,
+    reactions: Vec<ReactionCount>,
 }
 
 #[derive(Serialize)]
@@ -217,8 +424,17 @@ pub struct GetPostRes {
     title_norm: String,
     title: BlogPostTitle,
     body: BlogPostBody,
+    /// Sanitized html of the body without its front matter, only set when requested
+    body_html: Option<String>,
+    word_count: usize,
+    reading_time_minutes: usize,
+    #[serde(flatten)]
+    meta: PostMeta,
     user: MinUser,
+    /// Users mentioned in the body that resolved to an account
+    mentions: Vec<MinUser>,
     comments: Vec<GetComment>,
+    reactions: Vec<ReactionCount>,
 }
 
 #[derive(Deserialize)]
@@ -229,6 +445,9 @@ pub struct UpdateBlogPost {
 #[derive(Deserialize)]
 pub struct PostComment {
     body: BoundString<4, 4000>,
+    /// Comment on the same post being replied to, ignored when editing
+    #[serde(default)]
+    parent_id: Option<Ulid>,
 }
 
 #[derive(Serialize)]
@@ -236,8 +455,12 @@ pub struct GetComment {
     #[serde(flatten)]
     id_ts: IdAndTimestamps,
     post_id: Ulid,
+    parent_id: Option<Ulid>,
     author: MinUser,
     body: String,
+    /// Users mentioned in the body that resolved to an account
+    mentions: Vec<MinUser>,
+    reactions: Vec<ReactionCount>,
 }
 
 #[derive(Serialize)]
@@ -245,11 +468,441 @@ pub struct Updated {
     updated_at: DateTime<FixedOffset>,
 }
 
+#[derive(Serialize)]
+pub struct CommentEdited {
+    id: Ulid,
+    body: String,
+    mentions: Vec<MinUser>,
+    updated_at: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize)]
+pub struct CommentRemoved {
+    id: Ulid,
+}
+
+#[derive(Serialize)]
+pub struct PostEdited {
+    body: String,
+    mentions: Vec<MinUser>,
+    updated_at: DateTime<FixedOffset>,
+}
+
+/// The post was hidden or deleted, its live stream ends after this
+#[derive(Serialize)]
+pub struct PostRemoved {}
+
+/// Sent on a post's live stream, the variant is the server-sent event name
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum PostUpdate {
+    Comment(GetComment),
+    CommentEdited(CommentEdited),
+    CommentRemoved(CommentRemoved),
+    PostEdited(PostEdited),
+    PostRemoved(PostRemoved),
+}
+
+impl PostUpdate {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Comment(_) => "comment",
+            Self::CommentEdited(_) => "comment_edited",
+            Self::CommentRemoved(_) => "comment_removed",
+            Self::PostEdited(_) => "post_edited",
+            Self::PostRemoved(_) => "post_removed",
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct FollowList {
     users: Vec<MinUser>,
 }
 
+// Moderation
+#[derive(Deserialize)]
+pub struct ModerationReason {
+    reason: Option<BoundString<1, 1000>>,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeRole {
+    role: Role,
+}
+
+#[derive(Serialize)]
+pub struct ModerationActionItem {
+    id: i64,
+    moderator: Option<MinUser>,
+    kind: ModerationKind,
+    target_id: Ulid,
+    reason: Option<String>,
+    created_at: DateTime<FixedOffset>,
+}
+
+// Invites
+#[derive(Deserialize)]
+pub struct NewInvite {
+    /// Unset for unlimited uses, which only admins may create
+    max_uses: Option<i32>,
+    expires_in_days: Option<u16>,
+}
+
+#[derive(Serialize)]
+pub struct InviteItem {
+    code: String,
+    max_uses: Option<i32>,
+    uses: i32,
+    expires_at: Option<DateTime<FixedOffset>>,
+    revoked_at: Option<DateTime<FixedOffset>>,
+    created_at: DateTime<FixedOffset>,
+}
+
+// Reports
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportTarget {
+    Post,
+    Comment,
+}
+
+#[derive(Deserialize)]
+pub struct ReportContent {
+    reason: BoundString<4, 1000>,
+}
+
+#[derive(Deserialize)]
+pub struct ReportQuery {
+    #[serde(default)]
+    page: u32,
+}
+
+#[derive(Serialize)]
+pub struct ReportItem {
+    id: i64,
+    reporter: Option<MinUser>,
+    reason: String,
+    created_at: DateTime<FixedOffset>,
+}
+
+/// Open reports against a single post or comment
+#[derive(Serialize)]
+pub struct ReportGroup {
+    kind: ReportTarget,
+    target_id: Ulid,
+    author: MinUser,
+    /// Post title or the start of the comment
+    excerpt: String,
+    hidden: bool,
+    reports: Vec<ReportItem>,
+}
+
+#[derive(Serialize)]
+pub struct ReportsResolved {
+    resolved: i64,
+}
+
+// Admin
+#[derive(Deserialize)]
+pub struct AdminUserQuery {
+    /// Matched against usernames and emails
+    q: Option<BoundString<1, 128>>,
+    role: Option<Role>,
+    suspended: Option<bool>,
+    #[serde(default)]
+    page: u32,
+}
+
+#[derive(Serialize)]
+pub struct AdminUser {
+    #[serde(flatten)]
+    min: MinUser,
+    email: String,
+    role: Role,
+    created_at: DateTime<FixedOffset>,
+    totp_enabled: bool,
+    suspended_at: Option<DateTime<FixedOffset>>,
+    suspension_reason: Option<String>,
+    deletion_scheduled_at: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Serialize)]
+pub struct ContentCounts {
+    posts: i64,
+    comments: i64,
+    followers: i64,
+    following: i64,
+}
+
+#[derive(Serialize)]
+pub struct AdminUserDetail {
+    #[serde(flatten)]
+    user: AdminUser,
+    counts: ContentCounts,
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    kind: Option<AuditKind>,
+    actor: Option<Ulid>,
+    target: Option<Ulid>,
+    ip: Option<IpAddr>,
+    after: Option<DateTime<FixedOffset>>,
+    before: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    page: u32,
+}
+
+#[derive(Serialize)]
+pub struct AuditEventItem {
+    id: i64,
+    kind: AuditKind,
+    actor_id: Option<Ulid>,
+    target_id: Option<Ulid>,
+    ip: Option<String>,
+    detail: Option<String>,
+    created_at: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize)]
+pub struct PasswordReset {
+    /// Shown once, the user should change it after logging in
+    temporary_password: String,
+}
+
+// Account
+#[derive(Serialize)]
+pub struct ExportPostVersion {
+    body: String,
+    created_at: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize)]
+pub struct ExportPost {
+    id: Ulid,
+    title: String,
+    title_norm: String,
+    created_at: DateTime<FixedOffset>,
+    versions: Vec<ExportPostVersion>,
+}
+
+#[derive(Serialize)]
+pub struct ExportComment {
+    #[serde(flatten)]
+    id_ts: IdAndTimestamps,
+    post_id: Ulid,
+    body: String,
+}
+
+#[derive(Serialize)]
+pub struct AccountExport {
+    #[serde(flatten)]
+    user: MinUser,
+    email: String,
+    created_at: DateTime<FixedOffset>,
+    exported_at: DateTime<FixedOffset>,
+    posts: Vec<ExportPost>,
+    comments: Vec<ExportComment>,
+    following: Vec<MinUser>,
+    followers: Vec<MinUser>,
+    saved_posts: Vec<Ulid>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccount {
+    password: SecretString,
+    /// Must repeat the account's username
+    confirm: Username,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeUsername {
+    username: Username,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeEmail {
+    email: Email,
+    password: SecretString,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmail {
+    token: BoundString<16, 128>,
+}
+
+#[derive(Serialize)]
+pub struct AccountDeletion {
+    /// Unset when the account was deleted immediately
+    deletion_scheduled_at: Option<DateTime<FixedOffset>>,
+}
+
+// Notifications
+#[derive(Deserialize)]
+pub struct NotificationQuery {
+    /// Only list notifications that have not been read
+    #[serde(default)]
+    unread: bool,
+    #[serde(default)]
+    page: u32,
+}
+
+#[derive(Serialize)]
+pub struct NotificationItem {
+    id: i64,
+    kind: NotificationKind,
+    /// Unset when the user that caused it was deleted
+    actor: Option<MinUser>,
+    post_id: Option<Ulid>,
+    comment_id: Option<Ulid>,
+    read_at: Option<DateTime<FixedOffset>>,
+    created_at: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize)]
+pub struct UnreadCount {
+    unread: i64,
+}
+
+#[derive(Serialize)]
+pub struct NotificationsRead {
+    read: i64,
+}
+
+// Socket
+#[derive(Deserialize)]
+pub struct SocketQuery {
+    /// Id of the last event received before reconnecting
+    last_event_id: Option<Ulid>,
+}
+
+#[derive(Serialize)]
+pub struct FollowedPost {
+    id: Ulid,
+    title: String,
+    title_norm: String,
+    author: MinUser,
+    created_at: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UserMessage {
+    Notification(NotificationItem),
+    /// Posted by an author the user follows
+    NewPost(FollowedPost),
+    /// The session was logged out remotely, the socket closes after this
+    SessionRevoked,
+    /// Events were missed, clients should fetch their state again
+    Resync,
+}
+
+#[derive(Serialize)]
+pub struct SocketEvent<'a> {
+    /// Unset for messages that cannot be resumed from
+    event_id: Option<Ulid>,
+    #[serde(flatten)]
+    message: &'a UserMessage,
+}
+
+// Webhooks
+#[derive(Deserialize)]
+pub struct NewWebhook {
+    url: BoundString<8, 2048>,
+    events: Vec<WebhookEvent>,
+    /// Receive events for every post instead of only your own, admins only
+    #[serde(default)]
+    site_wide: bool,
+}
+
+#[derive(Serialize)]
+pub struct WebhookItem {
+    id: Ulid,
+    url: String,
+    events: Vec<WebhookEvent>,
+    site_wide: bool,
+    created_at: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookCreated {
+    #[serde(flatten)]
+    webhook: WebhookItem,
+    /// Shown once, used to verify the signature of each delivery
+    secret: String,
+}
+
+#[derive(Serialize)]
+pub struct WebhookDeliveryItem {
+    id: i64,
+    event: WebhookEvent,
+    payload: String,
+    attempts: i32,
+    /// Status of the latest attempt, unset when no response was received
+    status_code: Option<i32>,
+    error: Option<String>,
+    delivered_at: Option<DateTime<FixedOffset>>,
+    created_at: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookPost {
+    id: Ulid,
+    title: String,
+    title_norm: String,
+}
+
+#[derive(Serialize)]
+pub struct WebhookComment {
+    id: Ulid,
+    body: String,
+}
+
+#[derive(Serialize)]
+pub struct WebhookPayload {
+    event: WebhookEvent,
+    /// Author of the post or comment
+    actor: Option<MinUser>,
+    post: Option<WebhookPost>,
+    comment: Option<WebhookComment>,
+    occurred_at: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize)]
+pub struct UploadItem {
+    id: Ulid,
+    /// Where the file is served, under `/media/`
+    url: String,
+    content_type: String,
+    size: i32,
+    name: Option<String>,
+    width: Option<i32>,
+    height: Option<i32>,
+    /// Resized copies of images larger than each variant
+    variants: Vec<UploadVariantItem>,
+    created_at: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize)]
+pub struct UploadVariantItem {
+    kind: UploadVariantKind,
+    /// Stable across formats, `/media/{uploadId}/{variant}`
+    url: String,
+    content_type: String,
+    size: i32,
+    width: i32,
+    height: i32,
+}
+
+#[derive(Serialize)]
+pub struct Uploads {
+    /// Bytes used by all of the user's uploads
+    used: i64,
+    quota: i64,
+    uploads: Vec<UploadItem>,
+}
+
 pub fn api_routes() -> Router<BlogDrownState> {
     Router::new().nest(
         "/v1",
@@ -257,6 +910,17 @@ pub fn api_routes() -> Router<BlogDrownState> {
             .nest("/auth", auth::routes())
             .nest("/blogs", blog::routes())
             .nest("/comments", comments::routes())
-            .nest("/follows", follows::routes()),
+            .nest("/follows", follows::routes())
+            .nest("/invites", invites::routes())
+            .nest("/account", account::routes())
+            .nest("/users", users::routes())
+            .nest("/notifications", notifications::routes())
+            .nest("/socket", socket::routes())
+            .nest("/webhooks", webhooks::routes())
+            .nest("/uploads", uploads::routes())
+            .nest("/highlight", highlight::routes())
+            .nest("/moderation", moderation::routes())
+            .nest("/reports", reports::routes())
+            .nest("/admin", admin::routes()),
     )
 }
@@ -0,0 +1,593 @@
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponseParts,
+    routing::{get, post, put},
+    Json, Router,
+};
+use axum_extra::extract::CookieJar;
+use chrono::{TimeDelta, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    api::{Error, MinUser},
+    auth::{self, password, RequireLogin},
+    bounded::BoundString,
+    mail::Mailer,
+    ratelimit::{AuthAttempts, Throttle},
+    BlogDrownState,
+};
+
+use super::{
+    expect_uuid, AccountDeletion, AccountExport, ApiError, ApiJson, ChangeEmail, ChangeUsername,
+    DeleteAccount, ExportComment, ExportPost, ExportPostVersion, IdAndTimestamps, Profile,
+    UserMessage, UserProfile, VerifyEmail,
+};
+
+const USERNAME_COOLDOWN_DAYS: i64 = 30;
+const EMAIL_TOKEN_HOURS: i64 = 24;
+
+fn is_web_url(url: &str) -> bool {
+    (url.starts_with("https://") || url.starts_with("http://"))
+        && !url.contains(|c: char| c.is_whitespace() || c.is_control())
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn mail_unavailable() -> ApiError {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(Error::new("Email cannot be sent at the moment")),
+    )
+}
+
+async fn send_email_verification(
+    mailer: &Mailer,
+    email: &str,
+    token: &str,
+) -> Result<(), ApiError> {
+    let body = format!(
+        "Someone, hopefully you, asked to change the email address of a BlogDrown account to this one.\n\n\
+         Your verification token is:\n\n{token}\n\n\
+         It expires in {EMAIL_TOKEN_HOURS} hours. If this was not you, ignore this email."
+    );
+
+    mailer
+        .send(email, "Verify your new email address", body)
+        .await
+        .map_err(|e| {
+            tracing::error!("failed to send email verification to {email}: {e}");
+            mail_unavailable()
+        })
+}
+
+async fn update_profile(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+    ApiJson(profile): ApiJson<Profile>,
+) -> Result<Json<UserProfile>, ApiError> {
+    use crate::prisma::user;
+
+    let mut err = Error::new("Profile Validation Error");
+
+    for (field, url) in [
+        ("avatar_url", &profile.avatar_url),
+        ("website", &profile.website),
+    ] {
+        if url.as_deref().is_some_and(|u| !is_web_url(u)) {
+            err.add(field, "Must be an http or https URL");
+        }
+    }
+
+    if !err.errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(err)));
+    }
+
+    let user = state
+        .prisma
+        .user()
+        .update(
+            user::id::equals(auth.uuid()),
+            vec![
+                user::display_name::set(profile.display_name.map(BoundString::into_inner)),
+                user::bio::set(profile.bio.map(BoundString::into_inner)),
+                user::avatar_url::set(profile.avatar_url.map(BoundString::into_inner)),
+                user::website::set(profile.website.map(BoundString::into_inner)),
+            ],
+        )
+        .select(user::select!({
+            id username created_at display_name bio avatar_url website
+        }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(user_profile!(user)))
+}
+
+async fn change_username(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+    ApiJson(change): ApiJson<ChangeUsername>,
+) -> Result<Json<UserProfile>, ApiError> {
+    use crate::prisma::user;
+
+    let user = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(auth.uuid()))
+        .select(user::select!({ username_changed_at }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    if let Some(changed) = user.username_changed_at {
+        let allowed_at = changed + TimeDelta::days(USERNAME_COOLDOWN_DAYS);
+
+        if allowed_at > Utc::now() {
+            let mut err = Error::new("Username was changed too recently");
+            err.add(
+                "username",
+                format!(
+                    "Username can be changed again after {}",
+                    allowed_at.to_rfc3339()
+                ),
+            );
+
+            return Err((StatusCode::BAD_REQUEST, Json(err)));
+        }
+    }
+
+    let existing = state
+        .prisma
+        .user()
+        .count(vec![
+            user::username::equals(change.username.clone().into_inner()),
+            user::id::not(auth.uuid()),
+        ])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if existing != 0 {
+        let mut err = Error::new("User Already Exists");
+        err.add(
+            "username",
+            format!("Username {} already exists", change.username),
+        );
+
+        return Err((StatusCode::BAD_REQUEST, Json(err)));
+    }
+
+    let user = state
+        .prisma
+        .user()
+        .update(
+            user::id::equals(auth.uuid()),
+            vec![
+                user::username::set(change.username.into_inner()),
+                user::username_changed_at::set(Some(Utc::now().into())),
+            ],
+        )
+        .select(user::select!({
+            id username created_at display_name bio avatar_url website
+        }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(user_profile!(user)))
+}
+
+async fn change_email(
+    _: Throttle<AuthAttempts>,
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+    ApiJson(change): ApiJson<ChangeEmail>,
+) -> Result<StatusCode, ApiError> {
+    use crate::prisma::user;
+
+    let Some(mailer) = &state.mailer else {
+        return Err(mail_unavailable());
+    };
+
+    let user = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(auth.uuid()))
+        .select(user::select!({ password }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    if password::verify(&change.password, &user.password, state.production).is_err() {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(Error::new("Bad Credentials")),
+        ));
+    }
+
+    let existing = state
+        .prisma
+        .user()
+        .count(vec![user::email::equals(change.email.clone().into_inner())])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if existing != 0 {
+        let mut err = Error::new("User Already Exists");
+        err.add("email", format!("Email {} already exists", change.email));
+
+        return Err((StatusCode::BAD_REQUEST, Json(err)));
+    }
+
+    let mut token = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut token);
+    let token = hex::encode(token);
+
+    state
+        .prisma
+        .user()
+        .update(
+            user::id::equals(auth.uuid()),
+            vec![
+                user::pending_email::set(Some(change.email.clone().into_inner())),
+                user::email_token_hash::set(Some(hash_token(&token))),
+                user::email_token_expires::set(Some(
+                    (Utc::now() + TimeDelta::hours(EMAIL_TOKEN_HOURS)).into(),
+                )),
+            ],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    send_email_verification(mailer, &change.email, &token).await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn verify_email(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+    ApiJson(verify): ApiJson<VerifyEmail>,
+) -> Result<(), ApiError> {
+    use crate::prisma::user;
+
+    let user = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(auth.uuid()))
+        .select(user::select!({ pending_email email_token_hash email_token_expires }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    let (Some(email), Some(hash), Some(expires)) = (
+        user.pending_email,
+        user.email_token_hash,
+        user.email_token_expires,
+    ) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(Error::new("No email change is pending")),
+        ));
+    };
+
+    if expires < Utc::now() || hash != hash_token(&verify.token) {
+        let mut err = Error::new("Email verification failed");
+        err.add("token", "Token is invalid or has expired");
+
+        return Err((StatusCode::BAD_REQUEST, Json(err)));
+    }
+
+    state
+        .prisma
+        .user()
+        .update(
+            user::id::equals(auth.uuid()),
+            vec![
+                user::email::set(email),
+                user::pending_email::set(None),
+                user::email_token_hash::set(None),
+                user::email_token_expires::set(None),
+            ],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(())
+}
+
+async fn export_account(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+) -> Result<(impl IntoResponseParts, Json<AccountExport>), ApiError> {
+    use crate::prisma::{blog_post_version, user};
+    use prisma_client_rust::Direction;
+
+    let user = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(auth.uuid()))
+        .select(user::select!({
+            id
+            username
+            email
+            created_at
+            posts: select {
+                id
+                title
+                title_norm
+                created_at
+                versions(vec![])
+                    .order_by(blog_post_version::created_at::order(Direction::Asc)): select { text created_at }
+            }
+            comments: select { id post_id text created_at updated_at }
+            following: select { id username }
+            followers: select { id username }
+            saved_posts: select { id }
+        }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    let min_user = |id: String, username: String| MinUser {
+        id: expect_uuid(&id),
+        username: BoundString::new_unchecked(username),
+    };
+
+    let export = AccountExport {
+        user: min_user(user.id, user.username),
+        email: user.email,
+        created_at: user.created_at,
+        exported_at: Utc::now().into(),
+        posts: user
+            .posts
+            .into_iter()
+            .map(|p| ExportPost {
+                id: expect_uuid(&p.id),
+                title: p.title,
+                title_norm: p.title_norm,
+                created_at: p.created_at,
+                versions: p
+                    .versions
+                    .into_iter()
+                    .map(|v| ExportPostVersion {
+                        body: v.text,
+                        created_at: v.created_at,
+                    })
+                    .collect(),
+            })
+            .collect(),
+        comments: user
+            .comments
+            .into_iter()
+            .map(|c| ExportComment {
+                id_ts: IdAndTimestamps {
+                    id: expect_uuid(&c.id),
+                    created_at: c.created_at,
+                    updated_at: c.updated_at,
+                },
+                post_id: expect_uuid(&c.post_id),
+                body: c.text,
+            })
+            .collect(),
+        following: user
+            .following
+            .into_iter()
+            .map(|f| min_user(f.id, f.username))
+            .collect(),
+        followers: user
+            .followers
+            .into_iter()
+            .map(|f| min_user(f.id, f.username))
+            .collect(),
+        saved_posts: user
+            .saved_posts
+            .into_iter()
+            .map(|p| expect_uuid(&p.id))
+            .collect(),
+    };
+
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"blogdrown-export.json\"",
+        )],
+        Json(export),
+    ))
+}
+
+async fn delete_account(
+    _: Throttle<AuthAttempts>,
+    auth: RequireLogin,
+    jar: CookieJar,
+    State(state): State<BlogDrownState>,
+    ApiJson(delete): ApiJson<DeleteAccount>,
+) -> Result<(CookieJar, Json<AccountDeletion>), ApiError> {
+    use crate::prisma::user;
+
+    let user = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(auth.uuid()))
+        .select(user::select!({ username password }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    if password::verify(&delete.password, &user.password, state.production).is_err() {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(Error::new("Bad Credentials")),
+        ));
+    }
+
+    if *delete.confirm != user.username {
+        let mut err = Error::new("Account deletion was not confirmed");
+        err.add("confirm", "Must match your username");
+
+        return Err((StatusCode::BAD_REQUEST, Json(err)));
+    }
+
+    if state.deletion_grace.is_zero() {
+        let stored = stored_keys(&state, vec![user::id::equals(auth.uuid())])
+            .await
+            .map_err(Error::from_query)?;
+
+        state
+            .prisma
+            .user()
+            .delete(user::id::equals(auth.uuid()))
+            .exec()
+            .await
+            .map_err(Error::from_query)?;
+
+        super::remove_stored(&state, stored).await;
+
+        state
+            .live
+            .publish_user(vec![auth.id], UserMessage::SessionRevoked);
+
+        return Ok((
+            auth::clear_session(jar, state.production),
+            Json(AccountDeletion {
+                deletion_scheduled_at: None,
+            }),
+        ));
+    }
+
+    let now = Utc::now();
+
+    let scheduled = state
+        .prisma
+        .user()
+        .update(
+            user::id::equals(auth.uuid()),
+            vec![
+                user::deletion_scheduled_at::set(Some((now + state.deletion_grace).into())),
+                // other devices must log in again, which also lets the owner cancel
+                user::sessions_valid_after::set(Some(now.into())),
+            ],
+        )
+        .select(user::select!({ deletion_scheduled_at }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    state
+        .live
+        .publish_user(vec![auth.id], UserMessage::SessionRevoked);
+
+    Ok((
+        auth::clear_session(jar, state.production),
+        Json(AccountDeletion {
+            deletion_scheduled_at: scheduled.deletion_scheduled_at,
+        }),
+    ))
+}
+
+async fn cancel_deletion(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+) -> Result<(), ApiError> {
+    use crate::prisma::user;
+
+    state
+        .prisma
+        .user()
+        .update(
+            user::id::equals(auth.uuid()),
+            vec![user::deletion_scheduled_at::set(None)],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(())
+}
+
+/// Keys of the files uploaded by the matching users, which cascading deletes leave in storage
+async fn stored_keys(
+    state: &BlogDrownState,
+    users: Vec<crate::prisma::user::WhereParam>,
+) -> Result<Vec<String>, prisma_client_rust::QueryError> {
+    use crate::prisma::upload;
+
+    let uploads = state
+        .prisma
+        .upload()
+        .find_many(vec![upload::owner::is(users)])
+        .select(upload::select!({ key variants: select { key } }))
+        .exec()
+        .await?;
+
+    Ok(uploads
+        .into_iter()
+        .flat_map(|u| std::iter::once(u.key).chain(u.variants.into_iter().map(|v| v.key)))
+        .collect())
+}
+
+/// Permanently deletes accounts whose grace period has run out,
+/// owned content is removed by the schema's cascading deletes
+pub async fn purge_scheduled_deletions(state: BlogDrownState) {
+    use crate::prisma::user;
+
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+
+    loop {
+        interval.tick().await;
+
+        let now = Utc::now();
+
+        let stored = match stored_keys(&state, vec![user::deletion_scheduled_at::lte(now.into())])
+            .await
+        {
+            Ok(stored) => stored,
+            Err(e) => {
+                tracing::error!("failed to look up uploads of scheduled account deletions: {e}");
+                continue;
+            }
+        };
+
+        let purged = state
+            .prisma
+            .user()
+            .delete_many(vec![user::deletion_scheduled_at::lte(now.into())])
+            .exec()
+            .await;
+
+        match purged {
+            Ok(0) => {}
+            Ok(n) => {
+                tracing::info!("purged {n} accounts scheduled for deletion");
+                super::remove_stored(&state, stored).await;
+            }
+            Err(e) => tracing::error!("failed to purge scheduled account deletions: {e}"),
+        }
+    }
+}
+
+pub fn routes() -> Router<BlogDrownState> {
+    Router::new()
+        .route("/profile", put(update_profile))
+        .route("/username", put(change_username))
+        .route("/email", put(change_email))
+        .route("/email/verify", post(verify_email))
+        .route("/export", get(export_account))
+        .route("/delete", post(delete_account))
+        .route("/delete/cancel", post(cancel_deletion))
+}
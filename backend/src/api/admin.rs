@@ -0,0 +1,458 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use prisma_client_rust::{and, or};
+use rand::{distributions::Alphanumeric, Rng};
+use secrecy::SecretString;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    api::{Error, MinUser},
+    audit,
+    auth::{password, Admin, RequireLogin, RequireRole},
+    bounded::BoundString,
+    ip::ClientIp,
+    prisma::{self, AuditKind, ModerationKind},
+    BlogDrownState,
+};
+
+use super::{
+    expect_uuid, moderation::record_action, AdminUser, AdminUserDetail, AdminUserQuery, ApiError,
+    ApiJson, AuditEventItem, AuditQuery, ContentCounts, ModerationReason, PasswordReset,
+    UserMessage,
+};
+
+const PAGE_SIZE: i64 = 50;
+
+prisma::user::select!(admin_user {
+    id username email role created_at totp_enabled
+    suspended_at suspension_reason deletion_scheduled_at
+});
+
+impl From<admin_user::Data> for AdminUser {
+    fn from(user: admin_user::Data) -> Self {
+        Self {
+            min: MinUser {
+                id: expect_uuid(&user.id),
+                username: BoundString::new_unchecked(user.username),
+            },
+            email: user.email,
+            role: user.role,
+            created_at: user.created_at,
+            totp_enabled: user.totp_enabled,
+            suspended_at: user.suspended_at,
+            suspension_reason: user.suspension_reason,
+            deletion_scheduled_at: user.deletion_scheduled_at,
+        }
+    }
+}
+
+fn not_self(admin: &RequireRole<Admin>, uid: Ulid, action: &str) -> Result<(), ApiError> {
+    if uid == admin.login.id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(Error::new(format!("You cannot {action} your own account"))),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn list_users(
+    _: RequireRole<Admin>,
+    State(state): State<BlogDrownState>,
+    Query(query): Query<AdminUserQuery>,
+) -> Result<Json<Vec<AdminUser>>, ApiError> {
+    use prisma::{user, QueryMode};
+    use prisma_client_rust::Direction;
+
+    let mut filter = vec![];
+
+    if let Some(q) = query.q {
+        let q = q.into_inner();
+
+        filter.push(or![
+            and![
+                user::username::contains(q.clone()),
+                user::username::mode(QueryMode::Insensitive)
+            ],
+            and![
+                user::email::contains(q),
+                user::email::mode(QueryMode::Insensitive)
+            ]
+        ]);
+    }
+
+    if let Some(role) = query.role {
+        filter.push(user::role::equals(role));
+    }
+
+    match query.suspended {
+        Some(true) => filter.push(user::suspended_at::not(None)),
+        Some(false) => filter.push(user::suspended_at::equals(None)),
+        None => {}
+    }
+
+    let users = state
+        .prisma
+        .user()
+        .find_many(filter)
+        .order_by(user::created_at::order(Direction::Desc))
+        .skip(i64::from(query.page) * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .select(admin_user::select())
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(users.into_iter().map(AdminUser::from).collect()))
+}
+
+async fn get_user(
+    _: RequireRole<Admin>,
+    Path(uid): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<AdminUserDetail>, ApiError> {
+    use prisma::{blog_post, comment, user};
+
+    let id = Uuid::from(uid).to_string();
+
+    let user = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(id.clone()))
+        .select(admin_user::select())
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    let (posts, comments, followers, following) = tokio::try_join!(
+        state
+            .prisma
+            .blog_post()
+            .count(vec![blog_post::owner_id::equals(id.clone())])
+            .exec(),
+        state
+            .prisma
+            .comment()
+            .count(vec![comment::author_id::equals(id.clone())])
+            .exec(),
+        state
+            .prisma
+            .user()
+            .count(vec![user::following::some(vec![user::id::equals(
+                id.clone()
+            )])])
+            .exec(),
+        state
+            .prisma
+            .user()
+            .count(vec![user::followers::some(vec![user::id::equals(id)])])
+            .exec(),
+    )
+    .map_err(Error::from_query)?;
+
+    Ok(Json(AdminUserDetail {
+        user: user.into(),
+        counts: ContentCounts {
+            posts,
+            comments,
+            followers,
+            following,
+        },
+    }))
+}
+
+/// Suspends the user and records the action, returns false if the user does not exist
+/// or is already suspended
+pub(super) async fn suspend(
+    state: &BlogDrownState,
+    moderator: &RequireLogin,
+    uid: Ulid,
+    reason: Option<String>,
+) -> Result<bool, ApiError> {
+    use prisma::user;
+
+    let updated = state
+        .prisma
+        .user()
+        .update_many(
+            vec![
+                user::id::equals(Uuid::from(uid).to_string()),
+                user::suspended_at::equals(None),
+            ],
+            vec![
+                user::suspended_at::set(Some(Utc::now().into())),
+                user::suspension_reason::set(reason.clone()),
+            ],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if updated == 0 {
+        return Ok(false);
+    }
+
+    state
+        .live
+        .publish_user(vec![uid], UserMessage::SessionRevoked);
+
+    record_action(state, moderator, ModerationKind::SuspendUser, uid, reason).await;
+
+    Ok(true)
+}
+
+async fn suspend_user(
+    admin: RequireRole<Admin>,
+    Path(uid): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+    ApiJson(reason): ApiJson<ModerationReason>,
+) -> Result<(), ApiError> {
+    not_self(&admin, uid, "suspend")?;
+
+    let reason = reason.reason.map(BoundString::into_inner);
+
+    if !suspend(&state, &admin.login, uid, reason).await? {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(Error::new("User does not exist or is already suspended")),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn unsuspend_user(
+    admin: RequireRole<Admin>,
+    Path(uid): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+    ApiJson(reason): ApiJson<ModerationReason>,
+) -> Result<(), ApiError> {
+    use prisma::user;
+
+    let updated = state
+        .prisma
+        .user()
+        .update_many(
+            vec![
+                user::id::equals(Uuid::from(uid).to_string()),
+                user::suspended_at::not(None),
+            ],
+            vec![
+                user::suspended_at::set(None),
+                user::suspension_reason::set(None),
+            ],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if updated == 0 {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(Error::new("User does not exist or is not suspended")),
+        ));
+    }
+
+    record_action(
+        &state,
+        &admin.login,
+        ModerationKind::UnsuspendUser,
+        uid,
+        reason.reason.map(BoundString::into_inner),
+    )
+    .await;
+
+    Ok(())
+}
+
+async fn force_logout(
+    admin: RequireRole<Admin>,
+    Path(uid): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+    ApiJson(reason): ApiJson<ModerationReason>,
+) -> Result<(), ApiError> {
+    use prisma::user;
+
+    not_self(&admin, uid, "force logout")?;
+
+    let updated = state
+        .prisma
+        .user()
+        .update_many(
+            vec![user::id::equals(Uuid::from(uid).to_string())],
+            vec![user::sessions_valid_after::set(Some(Utc::now().into()))],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if updated == 0 {
+        return Err(Error::not_found());
+    }
+
+    state
+        .live
+        .publish_user(vec![uid], UserMessage::SessionRevoked);
+
+    record_action(
+        &state,
+        &admin.login,
+        ModerationKind::ForceLogout,
+        uid,
+        reason.reason.map(BoundString::into_inner),
+    )
+    .await;
+
+    Ok(())
+}
+
+/// Replaces the user's password with a random one and revokes their sessions
+async fn reset_password(
+    admin: RequireRole<Admin>,
+    ClientIp(ip): ClientIp,
+    Path(uid): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+    ApiJson(reason): ApiJson<ModerationReason>,
+) -> Result<Json<PasswordReset>, ApiError> {
+    use prisma::user;
+
+    not_self(&admin, uid, "reset the password of")?;
+
+    let temporary: String = rand::thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(20)
+        .map(char::from)
+        .collect();
+
+    let updated = state
+        .prisma
+        .user()
+        .update_many(
+            vec![user::id::equals(Uuid::from(uid).to_string())],
+            vec![
+                user::password::set(password::hash(
+                    &SecretString::from(temporary.clone()),
+                    state.production,
+                )),
+                user::sessions_valid_after::set(Some(Utc::now().into())),
+            ],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if updated == 0 {
+        return Err(Error::not_found());
+    }
+
+    state
+        .live
+        .publish_user(vec![uid], UserMessage::SessionRevoked);
+
+    audit::record(
+        &state,
+        AuditKind::PasswordChange,
+        Some(admin.login.id),
+        Some(uid),
+        Some(ip),
+        Some("reset by admin".to_owned()),
+    )
+    .await;
+
+    record_action(
+        &state,
+        &admin.login,
+        ModerationKind::ResetPassword,
+        uid,
+        reason.reason.map(BoundString::into_inner),
+    )
+    .await;
+
+    Ok(Json(PasswordReset {
+        temporary_password: temporary,
+    }))
+}
+
+async fn list_audit_events(
+    _: RequireRole<Admin>,
+    State(state): State<BlogDrownState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEventItem>>, ApiError> {
+    use prisma::audit_event;
+    use prisma_client_rust::Direction;
+
+    let mut filter = vec![];
+
+    if let Some(kind) = query.kind {
+        filter.push(audit_event::kind::equals(kind));
+    }
+
+    if let Some(actor) = query.actor {
+        filter.push(audit_event::actor_id::equals(Some(
+            Uuid::from(actor).to_string(),
+        )));
+    }
+
+    if let Some(target) = query.target {
+        filter.push(audit_event::target_id::equals(Some(
+            Uuid::from(target).to_string(),
+        )));
+    }
+
+    if let Some(ip) = query.ip {
+        filter.push(audit_event::ip::equals(Some(ip.to_string())));
+    }
+
+    if let Some(after) = query.after {
+        filter.push(audit_event::created_at::gte(after));
+    }
+
+    if let Some(before) = query.before {
+        filter.push(audit_event::created_at::lt(before));
+    }
+
+    let events = state
+        .prisma
+        .audit_event()
+        .find_many(filter)
+        .order_by(audit_event::id::order(Direction::Desc))
+        .skip(i64::from(query.page) * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(
+        events
+            .into_iter()
+            .map(|e| AuditEventItem {
+                id: e.id,
+                kind: e.kind,
+                actor_id: e.actor_id.as_deref().map(expect_uuid),
+                target_id: e.target_id.as_deref().map(expect_uuid),
+                ip: e.ip,
+                detail: e.detail,
+                created_at: e.created_at,
+            })
+            .collect(),
+    ))
+}
+
+pub fn routes() -> Router<BlogDrownState> {
+    Router::new()
+        .route("/audit", get(list_audit_events))
+        .route("/users", get(list_users))
+        .route("/users/:userId", get(get_user))
+        .route("/users/:userId/suspend", post(suspend_user))
+        .route("/users/:userId/unsuspend", post(unsuspend_user))
+        .route("/users/:userId/logout", post(force_logout))
+        .route("/users/:userId/password", post(reset_password))
+}
@@ -0,0 +1,242 @@
+use std::{collections::BTreeMap, net::IpAddr};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{
+        header::{HeaderName, REFERER, USER_AGENT},
+        HeaderMap, StatusCode,
+    },
+    Json,
+};
+use chrono::{NaiveDate, TimeDelta, Utc};
+use hmac::Mac;
+use prisma_client_rust::{
+    prisma_errors::query_engine::UniqueKeyViolation, PrismaValue, QueryError, Raw,
+};
+use serde_derive::Deserialize;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    api::Error,
+    auth::RequireLogin,
+    prisma::{blog_post, post_view},
+    BlogDrownState,
+};
+
+use super::{AnalyticsQuery, ApiError, DailyAnalytics, PostAnalytics, ReferrerCount};
+
+const DEFAULT_DAYS: u16 = 30;
+const MAX_DAYS: u16 = 365;
+
+/// Pseudonymous visitor id, keyed with the server secret and scoped to the post
+/// so it can neither be reversed to an address nor correlated across posts
+fn visitor_hash(state: &BlogDrownState, post_id: &str, ip: IpAddr, user_agent: &str) -> String {
+    let mut mac = state.jwt_secret.clone();
+    mac.update(b"blogdrown-view:");
+
+    // length prefixed so no two different inputs hash the same bytes
+    for part in [post_id, &ip.to_string(), user_agent] {
+        mac.update(&(part.len() as u64).to_be_bytes());
+        mac.update(part.as_bytes());
+    }
+
+    hex::encode(&mac.finalize().into_bytes()[..16])
+}
+
+/// Reduces a `Referer` header to its host so no paths or queries are stored
+fn referrer_host(referer: &str) -> Option<String> {
+    let rest = referer
+        .strip_prefix("https://")
+        .or_else(|| referer.strip_prefix("http://"))?;
+
+    let host = rest.split(['/', '?', '#']).next()?;
+    let host = host.rsplit_once('@').map_or(host, |(_, h)| h);
+
+    (!host.is_empty()).then(|| host.to_lowercase())
+}
+
+/// Counts a view of the post in the background, repeated views on the same day are ignored
+pub(super) fn record_view(
+    state: &BlogDrownState,
+    post_id: String,
+    ip: IpAddr,
+    headers: &HeaderMap,
+) {
+    let header = |name: HeaderName| headers.get(name).and_then(|h| h.to_str().ok());
+
+    let visitor = visitor_hash(state, &post_id, ip, header(USER_AGENT).unwrap_or_default());
+    let referrer = header(REFERER).and_then(referrer_host);
+    let day = Utc::now().date_naive();
+
+    let state = state.clone();
+
+    tokio::spawn(async move {
+        let res = state
+            .prisma
+            .post_view()
+            .create(
+                blog_post::id::equals(post_id.clone()),
+                day.and_time(Default::default()).and_utc().into(),
+                visitor,
+                vec![post_view::referrer::set(referrer)],
+            )
+            .exec()
+            .await;
+
+        match res {
+            Err(e) if !e.is_prisma_error::<UniqueKeyViolation>() => {
+                tracing::warn!("failed to record view of BlogPost({post_id}): {e}");
+            }
+            _ => {}
+        }
+    });
+}
+
+#[derive(Deserialize)]
+struct ViewTotals {
+    views: i64,
+    unique_visitors: i64,
+}
+
+#[derive(Deserialize)]
+struct DayCount {
+    day: NaiveDate,
+    count: i64,
+}
+
+/// Rows of the post in `table` counted per day of `column`, with the post id and first day as `params`
+async fn per_day(
+    state: &BlogDrownState,
+    table: &str,
+    column: &str,
+    params: Vec<PrismaValue>,
+) -> Result<Vec<DayCount>, QueryError> {
+    state
+        .prisma
+        ._query_raw(Raw::new(
+            &format!(
+                r#"SELECT "{column}"::date::text AS "day", COUNT(*)::int AS "count"
+                FROM "{table}" WHERE "post_id" = {{}}::uuid AND "{column}" >= {{}}::date
+                GROUP BY 1"#
+            ),
+            params,
+        ))
+        .exec()
+        .await
+}
+
+pub(super) async fn post_analytics(
+    auth: RequireLogin,
+    Path(post_id): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<PostAnalytics>, ApiError> {
+    let id = Uuid::from(post_id).to_string();
+
+    let post = state
+        .prisma
+        .blog_post()
+        .find_unique(blog_post::id::equals(id.clone()))
+        .select(blog_post::select!({ owner_id }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    if post.owner_id != auth.uuid() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(Error::new(
+                "Only the author may view analytics for this blogpost",
+            )),
+        ));
+    }
+
+    let days = query.days.unwrap_or(DEFAULT_DAYS).clamp(1, MAX_DAYS);
+    let today = Utc::now().date_naive();
+    let first = today - TimeDelta::days(i64::from(days) - 1);
+
+    let params = || {
+        vec![
+            PrismaValue::String(id.clone()),
+            PrismaValue::String(first.to_string()),
+        ]
+    };
+
+    let (totals, mut referrers, views, comments, reactions) = tokio::try_join!(
+        state
+            .prisma
+            ._query_raw::<ViewTotals>(Raw::new(
+                r#"SELECT COUNT(*)::int AS "views", COUNT(DISTINCT "visitor")::int AS "unique_visitors"
+                FROM "PostView" WHERE "post_id" = {}::uuid AND "day" >= {}::date"#,
+                params(),
+            ))
+            .exec(),
+        state
+            .prisma
+            ._query_raw::<ReferrerCount>(Raw::new(
+                r#"SELECT "referrer", COUNT(*)::int AS "views"
+                FROM "PostView" WHERE "post_id" = {}::uuid AND "day" >= {}::date
+                GROUP BY "referrer""#,
+                params(),
+            ))
+            .exec(),
+        per_day(&state, "PostView", "day", params()),
+        per_day(&state, "Comment", "created_at", params()),
+        per_day(&state, "Reaction", "created_at", params()),
+    )
+    .map_err(Error::from_query)?;
+
+    let mut daily: BTreeMap<NaiveDate, DailyAnalytics> = first
+        .iter_days()
+        .take(usize::from(days))
+        .map(|day| {
+            (
+                day,
+                DailyAnalytics {
+                    day,
+                    views: 0,
+                    comments: 0,
+                    reactions: 0,
+                },
+            )
+        })
+        .collect();
+
+    for v in views {
+        if let Some(d) = daily.get_mut(&v.day) {
+            d.views = v.count;
+        }
+    }
+
+    for c in comments {
+        if let Some(d) = daily.get_mut(&c.day) {
+            d.comments = c.count;
+        }
+    }
+
+    for r in reactions {
+        if let Some(d) = daily.get_mut(&r.day) {
+            d.reactions = r.count;
+        }
+    }
+
+    referrers.sort_by(|a, b| {
+        b.views
+            .cmp(&a.views)
+            .then_with(|| a.referrer.cmp(&b.referrer))
+    });
+
+    let totals = totals.into_iter().next().unwrap_or(ViewTotals {
+        views: 0,
+        unique_visitors: 0,
+    });
+
+    Ok(Json(PostAnalytics {
+        views: totals.views,
+        unique_visitors: totals.unique_visitors,
+        daily: daily.into_values().collect(),
+        referrers,
+    }))
+}
@@ -1,33 +1,61 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+};
 
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
+    response::sse::{self, KeepAlive, Sse},
     routing::{get, post, put},
     Json, Router,
 };
+use tokio_stream::Stream;
 use ulid::Ulid;
 use uuid::Uuid;
 
 use crate::{
-    api::{Created, Error, GetComment, MinUser},
-    auth::RequireLogin,
+    api::{follows::is_blocked, moderation::record_action, Created, Error, GetComment, MinUser},
+    audit,
+    auth::{self, RequireLogin},
     bounded::BoundString,
-    prisma::blog_post_version,
+    events::{self, Event},
+    front_matter::{self, FrontMatter},
+    ip::ClientIp,
+    markdown,
+    prisma::{blog_post_version, AuditKind, ModerationKind, Role},
     BlogDrownState,
 };
 
 use super::{
-    ApiError, ApiJson, GetAllPostsItem, GetPost, GetPostRes, IdAndTimestamps, NewBlogPost,
-    NewBlogPostRes, PostComment, UpdateBlogPost, Updated,
+    analytics, expect_uuid, mentions,
+    reactions::{self, Target},
+    ApiError, ApiJson, FollowedPost, GetAllPosts, GetAllPostsItem, GetPost, GetPostRes,
+    IdAndTimestamps, NewBlogPost, NewBlogPostRes, PostComment, PostEdited, PostMeta, PostRemoved,
+    PostSort, PostUpdate, UpdateBlogPost, Updated, UserMessage,
 };
 
 fn title_normalize(s: &str) -> String {
     s.to_lowercase().replace(' ', "_")
 }
 
-fn expect_uuid(s: &str) -> Ulid {
-    Ulid::from(s.parse::<Uuid>().expect("Database stores uuid"))
+/// The body's front matter, stored on its version
+fn front_matter_params(body: &str) -> Result<Vec<blog_post_version::SetParam>, ApiError> {
+    let FrontMatter {
+        summary,
+        cover_image,
+        tags,
+        canonical_url,
+        language,
+    } = front_matter::parse(body).map_err(|e| (StatusCode::BAD_REQUEST, Json(e)))?;
+
+    Ok(vec![
+        blog_post_version::summary::set(summary),
+        blog_post_version::cover_image::set(cover_image),
+        blog_post_version::tags::set(tags),
+        blog_post_version::canonical_url::set(canonical_url),
+        blog_post_version::language::set(language),
+    ])
 }
 
 async fn create_post(
@@ -39,6 +67,7 @@ async fn create_post(
 
     let id = Uuid::now_v7();
     let norm = title_normalize(&post.title);
+    let meta = front_matter_params(&post.body)?;
 
     let post_head = state
         .prisma
@@ -60,12 +89,63 @@ async fn create_post(
         .create(
             blog_post::UniqueWhereParam::IdEquals(post_head.id),
             post.body.into_inner(),
-            vec![],
+            meta,
         )
         .exec()
         .await
         .map_err(Error::from_query)?;
 
+    let post_id = Ulid::from(id);
+    mentions::sync(
+        &state,
+        Target::Post,
+        post_id,
+        post_id,
+        auth.id,
+        front_matter::strip(&latest.text),
+    )
+    .await;
+
+    events::dispatch(
+        &state,
+        Event::PostCreated {
+            post_id,
+            author: auth.id,
+        },
+    )
+    .await;
+
+    // the post exists by now, so failing to tell followers must not fail the request
+    let author = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(auth.uuid()))
+        .select(user::select!({ username followers: select { id } }))
+        .exec()
+        .await;
+
+    match author {
+        Ok(Some(author)) => state.live.publish_user(
+            author
+                .followers
+                .iter()
+                .map(|f| expect_uuid(&f.id))
+                .collect(),
+            UserMessage::NewPost(FollowedPost {
+                id: post_id,
+                title: post_head.title,
+                title_norm: norm.clone(),
+                author: MinUser {
+                    id: auth.id,
+                    username: BoundString::new_unchecked(author.username),
+                },
+                created_at: post_head.created_at,
+            }),
+        ),
+        Ok(None) => {}
+        Err(e) => tracing::error!("failed to look up followers for BlogPost({post_id}): {e}"),
+    }
+
     Ok(Created::json(NewBlogPostRes {
         id_ts: IdAndTimestamps {
             id: Ulid::from(id),
@@ -76,7 +156,54 @@ async fn create_post(
     }))
 }
 
+/// Html of a post version, rendered and cached on the version unless already cached
+async fn rendered(
+    state: &BlogDrownState,
+    version: i64,
+    text: &str,
+    cached: Option<String>,
+) -> Result<String, ApiError> {
+    if let Some(html) = cached {
+        return Ok(html);
+    }
+
+    let text = front_matter::strip(text).to_owned();
+
+    let html = tokio::task::spawn_blocking(move || markdown::render(&text))
+        .await
+        .map_err(|e| {
+            tracing::error!("rendering BlogPostVersion({version}) panicked: {e}");
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Error::new("Could not render the post")),
+            )
+        })?;
+
+    // a failed cache write only means rendering again next time
+    if let Err(e) = state
+        .prisma
+        .blog_post_version()
+        .update_many(
+            vec![blog_post_version::id::equals(version)],
+            vec![
+                blog_post_version::html::set(Some(html.clone())),
+                blog_post_version::html_renderer::set(Some(markdown::RENDERER)),
+            ],
+        )
+        .exec()
+        .await
+    {
+        tracing::warn!("failed to cache html of BlogPostVersion({version}): {e}");
+    }
+
+    Ok(html)
+}
+
 async fn get_post(
+    viewer: Option<RequireLogin>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
     State(state): State<BlogDrownState>,
     Query(post): Query<GetPost>,
 ) -> Result<Json<GetPostRes>, ApiError> {
@@ -87,6 +214,15 @@ async fn get_post(
     use prisma_client_rust::Direction;
 
     let post_id = post.id;
+    let render_html = post.html;
+
+    let mut comment_filter = vec![comment::hidden_at::equals(None)];
+
+    if let Some(viewer) = &viewer {
+        comment_filter.push(comment::author::is(vec![user::muted_by::none(vec![
+            user::id::equals(viewer.uuid()),
+        ])]));
+    }
 
     let mut post = state
         .prisma
@@ -95,19 +231,36 @@ async fn get_post(
         .select(select!({
             versions(vec![])
                 .order_by(blog_post_version::created_at::order(Direction::Desc))
-                .take(1): select { id text created_at }
+                .take(1): select {
+                    id text html html_renderer created_at
+                    excerpt word_count excerpt_renderer excerpt_length
+                    summary cover_image tags canonical_url language
+                }
             owner_id
             owner: select { username }
-            comments(vec![]).order_by(comment::created_at::order(Direction::Desc))
+            comments(comment_filter).order_by(comment::created_at::order(Direction::Desc))
             title_norm
             title
             created_at
+            hidden_at
         }))
         .exec()
         .await
         .map_err(Error::from_query)?
         .ok_or_else(Error::not_found)?;
 
+    if post.hidden_at.is_some() {
+        let Some(viewer) = &viewer else {
+            return Err(Error::not_found());
+        };
+
+        if expect_uuid(&post.owner_id) != viewer.id
+            && !auth::at_least(viewer.role(&state).await?, Role::Moderator)
+        {
+            return Err(Error::not_found());
+        }
+    }
+
     let Some(latest) = post.versions.pop() else {
         tracing::warn!(
             "Database integrity: BlogPost({}) exists but has no version history",
@@ -117,6 +270,10 @@ async fn get_post(
         return Err(Error::not_found());
     };
 
+    if viewer.as_ref().map(|v| v.uuid()) != Some(post.owner_id.clone()) {
+        analytics::record_view(&state, Uuid::from(post_id).to_string(), ip, &headers);
+    }
+
     let authors = post
         .comments
         .iter()
@@ -138,6 +295,61 @@ async fn get_post(
         .map(|a| (a.id, a.username))
         .collect::<HashMap<String, String>>();
 
+    let post_uuid = Uuid::from(post_id).to_string();
+
+    let summarizable = Summarizable {
+        version: latest.id,
+        body: latest.text.clone(),
+        cached: cached_summary(
+            &state,
+            latest.excerpt,
+            latest.word_count,
+            latest.excerpt_renderer,
+            latest.excerpt_length,
+        ),
+    };
+
+    let summary = summarize(&state, vec![summarizable])
+        .await?
+        .pop()
+        .expect("one summary per body");
+
+    let body_html = if render_html {
+        let cached = latest
+            .html
+            .filter(|_| latest.html_renderer == Some(markdown::RENDERER));
+
+        Some(rendered(&state, latest.id, &latest.text, cached).await?)
+    } else {
+        None
+    };
+
+    let mut post_reactions = reactions::counts(
+        &state,
+        Target::Post,
+        vec![post_uuid.clone()],
+        viewer.as_ref(),
+    )
+    .await?;
+
+    let mut comment_reactions = reactions::counts(
+        &state,
+        Target::Comment,
+        post.comments.iter().map(|c| c.id.clone()).collect(),
+        viewer.as_ref(),
+    )
+    .await?;
+
+    let mut post_mentions =
+        mentions::resolved(&state, Target::Post, vec![post_uuid.clone()]).await?;
+
+    let mut comment_mentions = mentions::resolved(
+        &state,
+        Target::Comment,
+        post.comments.iter().map(|c| c.id.clone()).collect(),
+    )
+    .await?;
+
     Ok(Json(GetPostRes {
         id_ts: IdAndTimestamps {
             id: post_id,
@@ -147,10 +359,21 @@ async fn get_post(
         title: BoundString::new_unchecked(post.title),
         title_norm: post.title_norm,
         body: BoundString::new_unchecked(latest.text),
+        body_html,
+        word_count: summary.word_count,
+        reading_time_minutes: summary.reading_time_minutes,
+        meta: PostMeta {
+            summary: latest.summary,
+            cover_image: latest.cover_image,
+            tags: latest.tags,
+            canonical_url: latest.canonical_url,
+            language: latest.language,
+        },
         user: MinUser {
             id: expect_uuid(&post.owner_id),
             username: BoundString::new_unchecked(post.owner.username),
         },
+        mentions: post_mentions.remove(&post_uuid).unwrap_or_default(),
         comments: post
             .comments
             .into_iter()
@@ -161,6 +384,7 @@ async fn get_post(
                     updated_at: c.updated_at,
                 },
                 post_id,
+                parent_id: c.parent_id.as_deref().map(expect_uuid),
                 body: c.text,
                 author: MinUser {
                     id: expect_uuid(&c.author_id),
@@ -171,11 +395,61 @@ async fn get_post(
                             .unwrap_or_else(String::new),
                     ),
                 },
+                mentions: comment_mentions.remove(&c.id).unwrap_or_default(),
+                reactions: comment_reactions.remove(&c.id).unwrap_or_default(),
             })
             .collect(),
+        reactions: post_reactions.remove(&post_uuid).unwrap_or_default(),
     }))
 }
 
+/// Streams new, edited and removed comments and edits of the post as server-sent events
+async fn live_post(
+    viewer: Option<RequireLogin>,
+    Path(post_id): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, Infallible>>>, ApiError> {
+    use crate::prisma::{blog_post, user};
+
+    let post = state
+        .prisma
+        .blog_post()
+        .find_unique(blog_post::id::equals(Uuid::from(post_id).to_string()))
+        .select(blog_post::select!({ owner_id hidden_at }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    if post.hidden_at.is_some() {
+        let Some(viewer) = &viewer else {
+            return Err(Error::not_found());
+        };
+
+        if expect_uuid(&post.owner_id) != viewer.id
+            && !auth::at_least(viewer.role(&state).await?, Role::Moderator)
+        {
+            return Err(Error::not_found());
+        }
+    }
+
+    let muted = match &viewer {
+        Some(viewer) => state
+            .prisma
+            .user()
+            .find_unique(user::id::equals(viewer.uuid()))
+            .select(user::select!({ muting: select { id } }))
+            .exec()
+            .await
+            .map_err(Error::from_query)?
+            .map(|u| u.muting.iter().map(|m| expect_uuid(&m.id)).collect())
+            .unwrap_or_default(),
+        None => vec![],
+    };
+
+    Ok(Sse::new(state.live.follow_post(post_id, muted)).keep_alive(KeepAlive::default()))
+}
+
 async fn update_post(
     auth: RequireLogin,
     Path(post_id): Path<Ulid>,
@@ -184,12 +458,12 @@ async fn update_post(
 ) -> Result<Json<Updated>, ApiError> {
     use crate::prisma::blog_post::{self, select};
 
-    let post_id = Uuid::from(post_id).to_string();
+    let post_uuid = Uuid::from(post_id).to_string();
 
     let post_head = state
         .prisma
         .blog_post()
-        .find_unique(blog_post::id::equals(post_id.clone()))
+        .find_unique(blog_post::id::equals(post_uuid.clone()))
         .select(select!({ owner_id }))
         .exec()
         .await
@@ -210,26 +484,58 @@ async fn update_post(
         ));
     };
 
-    let timestamp = state
+    let meta = front_matter_params(&update.body)?;
+
+    let version = state
         .prisma
         .blog_post_version()
         .create(
-            blog_post::id::equals(post_id),
+            blog_post::id::equals(post_uuid),
             update.body.into_inner(),
-            vec![],
+            meta,
         )
-        .select(blog_post_version::select!({ created_at }))
+        .select(blog_post_version::select!({ text created_at }))
         .exec()
         .await
         .map_err(Error::from_query)?;
 
+    let mentioned = mentions::sync(
+        &state,
+        Target::Post,
+        post_id,
+        post_id,
+        auth.id,
+        front_matter::strip(&version.text),
+    )
+    .await;
+
+    events::dispatch(
+        &state,
+        Event::PostUpdated {
+            post_id,
+            author: auth.id,
+        },
+    )
+    .await;
+
+    state.live.publish(
+        post_id,
+        None,
+        PostUpdate::PostEdited(PostEdited {
+            body: version.text,
+            mentions: mentioned,
+            updated_at: version.created_at,
+        }),
+    );
+
     Ok(Json(Updated {
-        updated_at: timestamp.created_at,
+        updated_at: version.created_at,
     }))
 }
 
 async fn delete_post(
     auth: RequireLogin,
+    ClientIp(ip): ClientIp,
     Path(post_id): Path<Ulid>,
     State(state): State<BlogDrownState>,
 ) -> Result<(), ApiError> {
@@ -239,7 +545,7 @@ async fn delete_post(
         .prisma
         .blog_post()
         .find_unique(blog_post::id::equals(Uuid::from(post_id).to_string()))
-        .select(select!({ owner_id }))
+        .select(select!({ owner_id title }))
         .exec()
         .await
         .map_err(Error::from_query)?
@@ -250,7 +556,9 @@ async fn delete_post(
         .parse::<Uuid>()
         .expect("Database stores uuid");
 
-    if Ulid::from(owner_id) != auth.id {
+    let moderating = Ulid::from(owner_id) != auth.id;
+
+    if moderating && !auth::at_least(auth.role(&state).await?, Role::Moderator) {
         return Err((
             StatusCode::FORBIDDEN,
             Json(Error::new(
@@ -267,6 +575,24 @@ async fn delete_post(
         .await
         .map_err(Error::from_query)?;
 
+    state
+        .live
+        .publish(post_id, None, PostUpdate::PostRemoved(PostRemoved {}));
+
+    audit::record(
+        &state,
+        AuditKind::PostDelete,
+        Some(auth.id),
+        Some(post_id),
+        Some(ip),
+        Some(post_head.title),
+    )
+    .await;
+
+    if moderating {
+        record_action(&state, &auth, ModerationKind::DeletePost, post_id, None).await;
+    }
+
     Ok(())
 }
 
@@ -287,95 +613,293 @@ async fn new_comment(
         .exec()
         .await
         .map_err(Error::from_query)?
+        .filter(|p| p.hidden_at.is_none())
         .ok_or_else(Error::not_found)?;
 
+    if is_blocked(&state, &post.owner_id, &auth.uuid()).await? {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(Error::new("You cannot comment on this post")),
+        ));
+    }
+
+    let parent = match comment.parent_id {
+        Some(parent_id) => {
+            let parent = state
+                .prisma
+                .comment()
+                .find_unique(comment::id::equals(Uuid::from(parent_id).to_string()))
+                .select(comment::select!({ id post_id author_id hidden_at }))
+                .exec()
+                .await
+                .map_err(Error::from_query)?
+                .filter(|c| c.post_id == post.id && c.hidden_at.is_none());
+
+            let Some(parent) = parent else {
+                let mut err = Error::new("Invalid reply");
+                err.add(
+                    "parent_id",
+                    "Replies must be to a visible comment on the same post",
+                );
+                return Err((StatusCode::BAD_REQUEST, Json(err)));
+            };
+
+            Some(parent)
+        }
+        None => None,
+    };
+
     let id = Uuid::now_v7();
 
-    let comment = state
+    let created = state
         .prisma
         .comment()
         .create(
             id.to_string(),
-            blog_post::id::equals(post.id),
+            blog_post::id::equals(post.id.clone()),
             user::id::equals(auth.uuid()),
             comment.body.into_inner(),
-            vec![],
+            parent
+                .iter()
+                .map(|p| comment::parent::connect(comment::id::equals(p.id.clone())))
+                .collect(),
         )
-        .select(comment::select!({ created_at }))
+        .select(comment::select!({
+            text
+            created_at
+            author: select { username }
+        }))
         .exec()
         .await
         .map_err(Error::from_query)?;
 
+    events::dispatch(
+        &state,
+        Event::CommentCreated {
+            post_id,
+            comment_id: Ulid::from(id),
+            author: auth.id,
+            post_owner: expect_uuid(&post.owner_id),
+            parent_author: parent.map(|p| expect_uuid(&p.author_id)),
+        },
+    )
+    .await;
+
+    let mentioned = mentions::sync(
+        &state,
+        Target::Comment,
+        Ulid::from(id),
+        post_id,
+        auth.id,
+        &created.text,
+    )
+    .await;
+
+    state.live.publish(
+        post_id,
+        Some(auth.id),
+        PostUpdate::Comment(GetComment {
+            id_ts: IdAndTimestamps {
+                id: Ulid::from(id),
+                created_at: created.created_at,
+                updated_at: created.created_at,
+            },
+            post_id,
+            parent_id: comment.parent_id,
+            author: MinUser {
+                id: auth.id,
+                username: BoundString::new_unchecked(created.author.username),
+            },
+            body: created.text,
+            mentions: mentioned,
+            reactions: vec![],
+        }),
+    );
+
     Ok(Created::json(IdAndTimestamps {
         id: Ulid::from(id),
-        created_at: comment.created_at,
-        updated_at: comment.created_at,
+        created_at: created.created_at,
+        updated_at: created.created_at,
     }))
 }
 
-/// Copied from rust stdlib unstable(feature = "round_char_boundary")
-pub fn floor_char_boundary(s: &str, index: usize) -> usize {
-    if index >= s.len() {
-        s.len()
-    } else {
-        let lower_bound = index.saturating_sub(3);
-        let new_index = s.as_bytes()[lower_bound..=index]
-            .iter()
-            // this is bit magic equivalent to b < 128 || b >= 192
-            .rposition(|b| (*b as i8) >= -0x40);
-
-        // SAFETY: we know that the character boundary will be within four bytes
-        unsafe { lower_bound + new_index.unwrap_unchecked() }
+/// A post version to summarize, along with its cached summary if still current
+struct Summarizable {
+    version: i64,
+    body: String,
+    cached: Option<markdown::Summary>,
+}
+
+/// The summary cached on a version, if made by this renderer for the configured excerpt length
+fn cached_summary(
+    state: &BlogDrownState,
+    excerpt: Option<String>,
+    word_count: Option<i32>,
+    renderer: Option<i32>,
+    length: Option<i32>,
+) -> Option<markdown::Summary> {
+    if renderer != Some(markdown::RENDERER) || length != Some(state.excerpt_length as i32) {
+        return None;
     }
+
+    Some(markdown::Summary::new(excerpt?, word_count? as usize))
+}
+
+/// Summaries of post versions, those not cached yet are made on a blocking thread
+/// as bodies may be long, and cached on their version
+async fn summarize(
+    state: &BlogDrownState,
+    versions: Vec<Summarizable>,
+) -> Result<Vec<markdown::Summary>, ApiError> {
+    use crate::prisma::blog_post_version;
+
+    let length = state.excerpt_length;
+
+    let (summaries, made): (Vec<_>, Vec<_>) = tokio::task::spawn_blocking(move || {
+        versions
+            .into_iter()
+            .map(|v| match v.cached {
+                Some(summary) => (summary, None),
+                None => (
+                    markdown::summarize(front_matter::strip(&v.body), length),
+                    Some(v.version),
+                ),
+            })
+            .unzip()
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("summarizing posts panicked: {e}");
+
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(Error::new("Could not summarize posts")),
+        )
+    })?;
+
+    let updates = summaries
+        .iter()
+        .zip(made)
+        .filter_map(|(summary, version)| {
+            Some(state.prisma.blog_post_version().update_many(
+                vec![blog_post_version::id::equals(version?)],
+                vec![
+                    blog_post_version::excerpt::set(Some(summary.excerpt.clone())),
+                    blog_post_version::word_count::set(Some(summary.word_count as i32)),
+                    blog_post_version::excerpt_renderer::set(Some(markdown::RENDERER)),
+                    blog_post_version::excerpt_length::set(Some(length as i32)),
+                ],
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    // a failed cache write only means summarizing again next time
+    if !updates.is_empty() {
+        if let Err(e) = state.prisma._batch(updates).await {
+            tracing::warn!("failed to cache post summaries: {e}");
+        }
+    }
+
+    Ok(summaries)
 }
 
 async fn get_all_posts(
+    viewer: Option<RequireLogin>,
     State(state): State<BlogDrownState>,
+    Query(query): Query<GetAllPosts>,
 ) -> Result<Json<Vec<GetAllPostsItem>>, ApiError> {
-    use crate::prisma::{blog_post, blog_post_version};
+    use crate::prisma::{blog_post, blog_post_version, user};
     use prisma_client_rust::Direction;
 
+    let mut filter = vec![blog_post::hidden_at::equals(None)];
+
+    if let Some(viewer) = &viewer {
+        filter.push(blog_post::owner::is(vec![user::muted_by::none(vec![
+            user::id::equals(viewer.uuid()),
+        ])]));
+    }
+
     let posts = state
         .prisma
         .blog_post()
-        .find_many(vec![])
+        .find_many(filter)
         .order_by(blog_post::created_at::order(Direction::Desc))
         .include(blog_post::include!({ versions(vec![]).order_by(blog_post_version::created_at::order(Direction::Desc)).take(1) owner }))
         .exec().await.map_err(Error::from_query)?;
 
-    Ok(Json(
-        posts
-            .into_iter()
-            .filter_map(|mut p| {
-                let Some(mut latest) = p.versions.pop() else {
-                    tracing::warn!(
-                        "Database integrity: BlogPost({}) exists but has no version history",
-                        expect_uuid(&p.id)
-                    );
-
-                    return None;
-                };
-
-                let bound = floor_char_boundary(&latest.text, 100);
-                latest.text.truncate(bound);
-
-                Some(GetAllPostsItem {
-                    id_ts: IdAndTimestamps {
-                        id: expect_uuid(&p.id),
-                        created_at: p.created_at,
-                        updated_at: latest.created_at,
-                    },
-                    title: p.title,
-                    title_norm: p.title_norm,
-                    user: MinUser {
-                        id: expect_uuid(&p.owner.id),
-                        username: BoundString::new_unchecked(p.owner.username),
-                    },
-                    partial_body: latest.text,
-                })
-            })
-            .collect(),
-    ))
+    let mut post_reactions = reactions::counts(
+        &state,
+        Target::Post,
+        posts.iter().map(|p| p.id.clone()).collect(),
+        viewer.as_ref(),
+    )
+    .await?;
+
+    let (posts, versions): (Vec<_>, Vec<_>) = posts
+        .into_iter()
+        .filter_map(|mut p| {
+            let Some(mut latest) = p.versions.pop() else {
+                tracing::warn!(
+                    "Database integrity: BlogPost({}) exists but has no version history",
+                    expect_uuid(&p.id)
+                );
+
+                return None;
+            };
+
+            let summarizable = Summarizable {
+                version: latest.id,
+                body: std::mem::take(&mut latest.text),
+                cached: cached_summary(
+                    &state,
+                    latest.excerpt.take(),
+                    latest.word_count,
+                    latest.excerpt_renderer,
+                    latest.excerpt_length,
+                ),
+            };
+
+            Some(((p, latest), summarizable))
+        })
+        .unzip();
+
+    let summaries = summarize(&state, versions).await?;
+
+    let mut items = posts
+        .into_iter()
+        .zip(summaries)
+        .map(|((p, latest), summary)| GetAllPostsItem {
+            id_ts: IdAndTimestamps {
+                id: expect_uuid(&p.id),
+                created_at: p.created_at,
+                updated_at: latest.created_at,
+            },
+            title: p.title,
+            title_norm: p.title_norm,
+            user: MinUser {
+                id: expect_uuid(&p.owner.id),
+                username: BoundString::new_unchecked(p.owner.username),
+            },
+            partial_body: latest.summary.clone().unwrap_or(summary.excerpt),
+            word_count: summary.word_count,
+            reading_time_minutes: summary.reading_time_minutes,
+            meta: PostMeta {
+                summary: latest.summary,
+                cover_image: latest.cover_image,
+                tags: latest.tags,
+                canonical_url: latest.canonical_url,
+                language: latest.language,
+            },
+            reactions: post_reactions.remove(&p.id).unwrap_or_default(),
+        })
+        .collect::<Vec<_>>();
+
+    if query.sort == PostSort::Popular {
+        // stable, so equally popular posts stay newest first
+        items.sort_by_key(|p| std::cmp::Reverse(p.reactions.iter().map(|r| r.count).sum::<i64>()));
+    }
+
+    Ok(Json(items))
 }
 
 pub fn routes() -> Router<BlogDrownState> {
@@ -383,5 +907,8 @@ pub fn routes() -> Router<BlogDrownState> {
         .route("/", post(create_post).get(get_all_posts))
         .route("/:post_id", put(update_post).delete(delete_post))
         .route("/:post_id/comments", post(new_comment))
+        .route("/:post_id/reactions", post(reactions::toggle_post))
+        .route("/:post_id/analytics", get(analytics::post_analytics))
+        .route("/:post_id/live", get(live_post))
         .route("/one", get(get_post))
 }
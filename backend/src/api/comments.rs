@@ -1,15 +1,26 @@
 use axum::{
     extract::{Path, State},
     http::StatusCode,
-    routing::put,
+    routing::{post, put},
     Json, Router,
 };
 use ulid::Ulid;
 use uuid::Uuid;
 
-use crate::{api::Error, auth::RequireLogin, BlogDrownState};
+use crate::{
+    api::{moderation::record_action, Error},
+    audit,
+    auth::{self, RequireLogin},
+    ip::ClientIp,
+    prisma::{AuditKind, ModerationKind, Role},
+    BlogDrownState,
+};
 
-use super::{ApiError, ApiJson, PostComment, Updated};
+use super::{
+    expect_uuid, mentions,
+    reactions::{self, Target},
+    ApiError, ApiJson, CommentEdited, CommentRemoved, PostComment, PostUpdate, Updated,
+};
 
 async fn update_comment(
     auth: RequireLogin,
@@ -29,7 +40,7 @@ async fn update_comment(
     let comment =
         tx.1.comment()
             .find_unique(comment::id::equals(Uuid::from(comment_id).to_string()))
-            .select(select!({ author_id }))
+            .select(select!({ author_id post_id }))
             .exec()
             .await
             .map_err(Error::from_query)?
@@ -52,19 +63,42 @@ async fn update_comment(
         ));
     };
 
+    let post_id = expect_uuid(&comment.post_id);
+
     let comment =
         tx.1.comment()
             .update(
                 comment::id::equals(Uuid::from(comment_id).to_string()),
                 vec![comment::text::set(edit.body.into_inner())],
             )
-            .select(select!({ updated_at }))
+            .select(select!({ text updated_at }))
             .exec()
             .await
             .map_err(Error::from_query)?;
 
     tx.0.commit(tx.1).await.map_err(Error::from_query)?;
 
+    let mentioned = mentions::sync(
+        &state,
+        Target::Comment,
+        comment_id,
+        post_id,
+        auth.id,
+        &comment.text,
+    )
+    .await;
+
+    state.live.publish(
+        post_id,
+        Some(auth.id),
+        PostUpdate::CommentEdited(CommentEdited {
+            id: comment_id,
+            body: comment.text,
+            mentions: mentioned,
+            updated_at: comment.updated_at,
+        }),
+    );
+
     Ok(Json(Updated {
         updated_at: comment.updated_at,
     }))
@@ -72,6 +106,7 @@ async fn update_comment(
 
 async fn delete_comment(
     auth: RequireLogin,
+    ClientIp(ip): ClientIp,
     Path(comment_id): Path<Ulid>,
     State(state): State<BlogDrownState>,
 ) -> Result<(), ApiError> {
@@ -87,7 +122,7 @@ async fn delete_comment(
     let comment =
         tx.1.comment()
             .find_unique(comment::id::equals(Uuid::from(comment_id).to_string()))
-            .select(select!({ author_id }))
+            .select(select!({ author_id post_id }))
             .exec()
             .await
             .map_err(Error::from_query)?
@@ -100,7 +135,9 @@ async fn delete_comment(
             .expect("database schema is uuid"),
     );
 
-    let true = author_id == auth.id else {
+    let moderating = author_id != auth.id;
+
+    if moderating && !auth::at_least(auth.role(&state).await?, Role::Moderator) {
         tx.0.rollback(tx.1).await.map_err(Error::from_query)?;
         return Err((
             StatusCode::FORBIDDEN,
@@ -108,7 +145,7 @@ async fn delete_comment(
                 "You do not have permission to delete this comment",
             )),
         ));
-    };
+    }
 
     tx.1.comment()
         .delete_many(vec![comment::id::equals(
@@ -120,9 +157,38 @@ async fn delete_comment(
 
     tx.0.commit(tx.1).await.map_err(Error::from_query)?;
 
+    state.live.publish(
+        expect_uuid(&comment.post_id),
+        Some(author_id),
+        PostUpdate::CommentRemoved(CommentRemoved { id: comment_id }),
+    );
+
+    if moderating {
+        audit::record(
+            &state,
+            AuditKind::CommentDelete,
+            Some(auth.id),
+            Some(comment_id),
+            Some(ip),
+            Some(format!("authored by {author_id}")),
+        )
+        .await;
+
+        record_action(
+            &state,
+            &auth,
+            ModerationKind::DeleteComment,
+            comment_id,
+            None,
+        )
+        .await;
+    }
+
     Ok(())
 }
 
 pub fn routes() -> Router<BlogDrownState> {
-    Router::new().route("/:commentId", put(update_comment).delete(delete_comment))
+    Router::new()
+        .route("/:commentId", put(update_comment).delete(delete_comment))
+        .route("/:commentId/reactions", post(reactions::toggle_comment))
 }
@@ -1,5 +1,6 @@
 use axum::{
     extract::{Path, State},
+    http::StatusCode,
     routing::{get, post},
     Json, Router,
 };
@@ -10,11 +11,45 @@ use crate::{
     api::{Error, MinUser},
     auth::RequireLogin,
     bounded::BoundString,
+    events::{self, Event},
     BlogDrownState,
 };
 
 use super::{ApiError, FollowList};
 
+/// Whether the user `blocker` has blocked `blocked`, both given as database ids
+pub(super) async fn is_blocked(
+    state: &BlogDrownState,
+    blocker: &str,
+    blocked: &str,
+) -> Result<bool, ApiError> {
+    use crate::prisma::user;
+
+    let count = state
+        .prisma
+        .user()
+        .count(vec![
+            user::id::equals(blocker.to_owned()),
+            user::blocking::some(vec![user::id::equals(blocked.to_owned())]),
+        ])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(count != 0)
+}
+
+fn not_self(auth: &RequireLogin, uid: Ulid) -> Result<(), ApiError> {
+    if auth.id == uid {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(Error::new("You cannot do this to yourself")),
+        ));
+    }
+
+    Ok(())
+}
+
 async fn add_follow(
     auth: RequireLogin,
     Path(uid): Path<Ulid>,
@@ -22,6 +57,26 @@ async fn add_follow(
 ) -> Result<(), ApiError> {
     use crate::prisma::user;
 
+    if is_blocked(&state, &Uuid::from(uid).to_string(), &auth.uuid()).await? {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(Error::new("You cannot follow this user")),
+        ));
+    }
+
+    // following again is a no-op and should not notify twice
+    let already = state
+        .prisma
+        .user()
+        .count(vec![
+            user::id::equals(auth.uuid()),
+            user::following::some(vec![user::id::equals(Uuid::from(uid).to_string())]),
+        ])
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        > 0;
+
     state
         .prisma
         .user()
@@ -35,6 +90,17 @@ async fn add_follow(
         .await
         .map_err(Error::from_query)?;
 
+    if !already {
+        events::dispatch(
+            &state,
+            Event::Followed {
+                follower: auth.id,
+                followee: uid,
+            },
+        )
+        .await;
+    }
+
     Ok(())
 }
 
@@ -89,6 +155,163 @@ async fn get_follows(
     }))
 }
 
+/// Blocks a user, removing any follows between the two of you
+async fn add_block(
+    auth: RequireLogin,
+    Path(uid): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+) -> Result<(), ApiError> {
+    use crate::prisma::user;
+
+    not_self(&auth, uid)?;
+
+    let target = || vec![user::id::equals(Uuid::from(uid).to_string())];
+
+    state
+        .prisma
+        .user()
+        .update(
+            user::id::equals(auth.uuid()),
+            vec![
+                user::blocking::connect(target()),
+                user::following::disconnect(target()),
+                user::followers::disconnect(target()),
+            ],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(())
+}
+
+async fn remove_block(
+    auth: RequireLogin,
+    Path(uid): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+) -> Result<(), ApiError> {
+    use crate::prisma::user;
+
+    state
+        .prisma
+        .user()
+        .update(
+            user::id::equals(auth.uuid()),
+            vec![user::blocking::disconnect(vec![user::id::equals(
+                Uuid::from(uid).to_string(),
+            )])],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(())
+}
+
+async fn get_blocks(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<FollowList>, ApiError> {
+    use crate::prisma::user;
+
+    let blocking = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(auth.uuid()))
+        .select(user::select!({ blocking: select { username id } }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    Ok(Json(FollowList {
+        users: blocking
+            .blocking
+            .into_iter()
+            .map(|b| MinUser {
+                id: Ulid::from(b.id.parse::<Uuid>().expect("db stores uuid")),
+                username: BoundString::new_unchecked(b.username),
+            })
+            .collect(),
+    }))
+}
+
+async fn add_mute(
+    auth: RequireLogin,
+    Path(uid): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+) -> Result<(), ApiError> {
+    use crate::prisma::user;
+
+    not_self(&auth, uid)?;
+
+    state
+        .prisma
+        .user()
+        .update(
+            user::id::equals(auth.uuid()),
+            vec![user::muting::connect(vec![user::id::equals(
+                Uuid::from(uid).to_string(),
+            )])],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(())
+}
+
+async fn remove_mute(
+    auth: RequireLogin,
+    Path(uid): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+) -> Result<(), ApiError> {
+    use crate::prisma::user;
+
+    state
+        .prisma
+        .user()
+        .update(
+            user::id::equals(auth.uuid()),
+            vec![user::muting::disconnect(vec![user::id::equals(
+                Uuid::from(uid).to_string(),
+            )])],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(())
+}
+
+async fn get_mutes(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<FollowList>, ApiError> {
+    use crate::prisma::user;
+
+    let muting = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(auth.uuid()))
+        .select(user::select!({ muting: select { username id } }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    Ok(Json(FollowList {
+        users: muting
+            .muting
+            .into_iter()
+            .map(|m| MinUser {
+                id: Ulid::from(m.id.parse::<Uuid>().expect("db stores uuid")),
+                username: BoundString::new_unchecked(m.username),
+            })
+            .collect(),
+    }))
+}
+
 //async fn following_feed(auth: RequireLogin, State(state): State<BlogDrownState>) -> Result<String, ApiError> {
 //    todo!()
 //}
@@ -97,5 +320,9 @@ pub fn routes() -> Router<BlogDrownState> {
     Router::new()
         .route("/:userId", post(add_follow).delete(remove_follow))
         .route("/", get(get_follows))
+        .route("/blocks", get(get_blocks))
+        .route("/blocks/:userId", post(add_block).delete(remove_block))
+        .route("/mutes", get(get_mutes))
+        .route("/mutes/:userId", post(add_mute).delete(remove_mute))
     //.route("/feed.rss", get(following_feed))
 }
@@ -0,0 +1,33 @@
+use axum::{extract::Path, http::header, response::IntoResponse, routing::get, Json, Router};
+
+use crate::{api::Error, highlight, BlogDrownState};
+
+use super::ApiError;
+
+/// Stylesheets only change when the server is upgraded
+const CACHE_CONTROL: &str = "public, max-age=86400";
+
+async fn list_themes() -> Json<Vec<&'static str>> {
+    Json(highlight::themes().collect())
+}
+
+async fn stylesheet(Path(file): Path<String>) -> Result<impl IntoResponse, ApiError> {
+    let css = file
+        .strip_suffix(".css")
+        .and_then(highlight::stylesheet)
+        .ok_or_else(Error::not_found)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/css; charset=utf-8"),
+            (header::CACHE_CONTROL, CACHE_CONTROL),
+        ],
+        css,
+    ))
+}
+
+pub fn routes() -> Router<BlogDrownState> {
+    Router::new()
+        .route("/themes", get(list_themes))
+        .route("/:theme", get(stylesheet))
+}
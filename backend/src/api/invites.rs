@@ -0,0 +1,208 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::{TimeDelta, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::{
+    api::{Created, Error},
+    auth::{self, RequireLogin},
+    prisma::{self, invite, PrismaClient, Role},
+    BlogDrownState,
+};
+
+use super::{ApiError, ApiJson, InviteItem, NewInvite};
+
+const CODE_LEN: usize = 16;
+
+fn invite_item(invite: invite::Data) -> InviteItem {
+    InviteItem {
+        code: invite.code,
+        max_uses: invite.max_uses,
+        uses: invite.uses,
+        expires_at: invite.expires_at,
+        revoked_at: invite.revoked_at,
+        created_at: invite.created_at,
+    }
+}
+
+/// Invite uses the owner has handed out, revoked and expired invites give back
+/// the uses that were never claimed
+async fn handed_out(prisma: &PrismaClient, owner: String) -> Result<i64, ApiError> {
+    let invites = prisma
+        .invite()
+        .find_many(vec![invite::creator_id::equals(Some(owner))])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    let now = Utc::now();
+
+    Ok(invites
+        .iter()
+        .map(|i| {
+            if i.revoked_at.is_some() || i.expires_at.is_some_and(|e| e < now) {
+                i.uses
+            } else {
+                i.max_uses.unwrap_or(i.uses)
+            }
+        })
+        .map(i64::from)
+        .sum())
+}
+
+async fn create_invite(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+    ApiJson(new): ApiJson<NewInvite>,
+) -> Result<Created<Json<InviteItem>>, ApiError> {
+    use prisma::user;
+    use prisma_client_rust::{raw, PrismaValue};
+
+    if new.max_uses.is_some_and(|max| max < 1) {
+        let mut err = Error::new("Invalid invite");
+        err.add("max_uses", "An invite must allow at least one use");
+        return Err((StatusCode::BAD_REQUEST, Json(err)));
+    }
+
+    let admin = auth::at_least(auth.role(&state).await?, Role::Admin);
+
+    // admins have no quota, everyone else must limit their invites
+    let quota_uses = match new.max_uses {
+        _ if admin => None,
+        Some(max_uses) => Some(max_uses),
+        None => {
+            let mut err = Error::new("Invalid invite");
+            err.add("max_uses", "Only admins may create unlimited invites");
+            return Err((StatusCode::FORBIDDEN, Json(err)));
+        }
+    };
+
+    let code: String = rand::thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(CODE_LEN)
+        .map(char::from)
+        .collect();
+
+    let tx = state
+        .prisma
+        ._transaction()
+        .begin()
+        .await
+        .map_err(Error::from_query)?;
+
+    if let Some(max_uses) = quota_uses {
+        // held until the transaction ends, other invites by the same user wait here
+        tx.1._execute_raw(raw!(
+            r#"SELECT 1 FROM "User" WHERE "id" = {}::uuid FOR UPDATE"#,
+            PrismaValue::String(auth.uuid())
+        ))
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+        let handed_out = handed_out(&tx.1, auth.uuid()).await?;
+
+        if handed_out + i64::from(max_uses) > state.invite_quota {
+            tx.0.rollback(tx.1).await.map_err(Error::from_query)?;
+
+            let mut err = Error::new("Invite quota exceeded");
+            err.add(
+                "max_uses",
+                format!(
+                    "You have {} of {} invite uses left",
+                    (state.invite_quota - handed_out).max(0),
+                    state.invite_quota
+                ),
+            );
+            return Err((StatusCode::FORBIDDEN, Json(err)));
+        }
+    }
+
+    let invite =
+        tx.1.invite()
+            .create(
+                code,
+                vec![
+                    invite::creator::connect(user::id::equals(auth.uuid())),
+                    invite::max_uses::set(new.max_uses),
+                    invite::expires_at::set(
+                        new.expires_in_days
+                            .map(|days| (Utc::now() + TimeDelta::days(days.into())).into()),
+                    ),
+                ],
+            )
+            .exec()
+            .await
+            .map_err(Error::from_query)?;
+
+    tx.0.commit(tx.1).await.map_err(Error::from_query)?;
+
+    Ok(Created::json(invite_item(invite)))
+}
+
+async fn list_invites(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<Vec<InviteItem>>, ApiError> {
+    use prisma_client_rust::Direction;
+
+    let invites = state
+        .prisma
+        .invite()
+        .find_many(vec![invite::creator_id::equals(Some(auth.uuid()))])
+        .order_by(invite::created_at::order(Direction::Desc))
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(invites.into_iter().map(invite_item).collect()))
+}
+
+async fn revoke_invite(
+    auth: RequireLogin,
+    Path(code): Path<String>,
+    State(state): State<BlogDrownState>,
+) -> Result<(), ApiError> {
+    let invite = state
+        .prisma
+        .invite()
+        .find_unique(invite::code::equals(code.clone()))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    if invite.creator_id.as_deref() != Some(auth.uuid().as_str())
+        && !auth::at_least(auth.role(&state).await?, Role::Admin)
+    {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(Error::new(
+                "You do not have permission to revoke this invite",
+            )),
+        ));
+    }
+
+    state
+        .prisma
+        .invite()
+        .update_many(
+            vec![invite::code::equals(code), invite::revoked_at::equals(None)],
+            vec![invite::revoked_at::set(Some(Utc::now().into()))],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(())
+}
+
+pub fn routes() -> Router<BlogDrownState> {
+    Router::new()
+        .route("/", get(list_invites).post(create_invite))
+        .route("/:code", delete(revoke_invite))
+}
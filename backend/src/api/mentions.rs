@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use prisma_client_rust::QueryError;
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    api::{expect_uuid, Error, MinUser},
+    bounded::BoundString,
+    events, markdown,
+    prisma::{mention, user},
+    BlogDrownState,
+};
+
+use super::{reactions::Target, ApiError};
+
+const MIN_LEN: usize = 6;
+const MAX_LEN: usize = 64;
+/// Further mentions in a body are ignored, so a single post cannot notify the whole site
+const MAX_MENTIONS: usize = 20;
+
+fn target_filter(target: Target, ids: Vec<String>) -> mention::WhereParam {
+    match target {
+        Target::Post => mention::post_id::in_vec(ids),
+        Target::Comment => mention::comment_id::in_vec(ids),
+    }
+}
+
+fn is_username_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// The text of the markdown outside of code, where `@Override` is not a mention
+fn prose(body: &str) -> String {
+    let mut text = String::with_capacity(body.len());
+    let mut in_code = false;
+
+    for event in Parser::new_ext(body, markdown::options()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(TagEnd::CodeBlock) => in_code = false,
+            Event::Text(t) if !in_code => text.push_str(&t),
+            // inline code, html and block boundaries separate the text around them
+            _ => text.push(' '),
+        }
+    }
+
+    text
+}
+
+/// Usernames written as `@username`, not preceded by a word character so email addresses are skipped
+fn parse(body: &str) -> Vec<String> {
+    let body = prose(body);
+    let mut found: Vec<String> = vec![];
+    let mut prev = None;
+
+    for (i, c) in body.char_indices() {
+        if c == '@' && !prev.is_some_and(is_username_char) {
+            let rest = &body[i + 1..];
+            let end = rest.find(|c| !is_username_char(c)).unwrap_or(rest.len());
+            // a trailing period usually ends the sentence rather than the name
+            let name = rest[..end].trim_end_matches('.');
+
+            if (MIN_LEN..=MAX_LEN).contains(&name.chars().count())
+                && !found.iter().any(|f| f == name)
+            {
+                found.push(name.to_owned());
+
+                if found.len() == MAX_MENTIONS {
+                    break;
+                }
+            }
+        }
+
+        prev = Some(c);
+    }
+
+    found
+}
+
+/// Removes mentions no longer in the body and stores the new ones
+async fn store(
+    state: &BlogDrownState,
+    target: Target,
+    db_id: &str,
+    removed: Vec<String>,
+    added: &[String],
+) -> Result<(), QueryError> {
+    if !removed.is_empty() {
+        state
+            .prisma
+            .mention()
+            .delete_many(vec![
+                target_filter(target, vec![db_id.to_owned()]),
+                mention::user_id::in_vec(removed),
+            ])
+            .exec()
+            .await?;
+    }
+
+    if added.is_empty() {
+        return Ok(());
+    }
+
+    state
+        .prisma
+        .mention()
+        .create_many(
+            added
+                .iter()
+                .map(|u| {
+                    let link = match target {
+                        Target::Post => mention::post_id::set(Some(db_id.to_owned())),
+                        Target::Comment => mention::comment_id::set(Some(db_id.to_owned())),
+                    };
+
+                    mention::create_unchecked(u.clone(), vec![link])
+                })
+                .collect(),
+        )
+        .exec()
+        .await?;
+
+    Ok(())
+}
+
+/// Replaces the stored mentions of a post or comment with the ones in its new body,
+/// users that were not mentioned before are notified
+///
+/// Returns the users now mentioned. The post or comment is already saved by then,
+/// so failures are only logged and leave the previous mentions in place
+pub(super) async fn sync(
+    state: &BlogDrownState,
+    target: Target,
+    id: Ulid,
+    post_id: Ulid,
+    author: Ulid,
+    body: &str,
+) -> Vec<MinUser> {
+    let db_id = Uuid::from(id).to_string();
+    let names = parse(body);
+
+    let model = match target {
+        Target::Post => "BlogPost",
+        Target::Comment => "Comment",
+    };
+
+    let found = tokio::try_join!(
+        state
+            .prisma
+            .user()
+            .find_many(vec![user::username::in_vec(names)])
+            .select(user::select!({ id username }))
+            .exec(),
+        state
+            .prisma
+            .mention()
+            .find_many(vec![target_filter(target, vec![db_id.clone()])])
+            .select(mention::select!({ user_id }))
+            .exec(),
+    );
+
+    let (resolved, existing) = match found {
+        Ok(found) => found,
+        Err(e) => {
+            tracing::error!("failed to look up mentions in {model}({db_id}): {e}");
+            return vec![];
+        }
+    };
+
+    let existing: Vec<String> = existing.into_iter().map(|m| m.user_id).collect();
+
+    let removed: Vec<String> = existing
+        .iter()
+        .filter(|e| !resolved.iter().any(|u| &u.id == *e))
+        .cloned()
+        .collect();
+    let added: Vec<String> = resolved
+        .iter()
+        .filter(|u| !existing.contains(&u.id))
+        .map(|u| u.id.clone())
+        .collect();
+
+    let resolved = resolved
+        .into_iter()
+        .map(|u| MinUser {
+            id: expect_uuid(&u.id),
+            username: BoundString::new_unchecked(u.username),
+        })
+        .collect();
+
+    if let Err(e) = store(state, target, &db_id, removed, &added).await {
+        tracing::error!("failed to store mentions in {model}({db_id}): {e}");
+        return resolved;
+    }
+
+    for user in added {
+        events::dispatch(
+            state,
+            events::Event::Mentioned {
+                user: expect_uuid(&user),
+                by: author,
+                post_id,
+                comment_id: matches!(target, Target::Comment).then_some(id),
+            },
+        )
+        .await;
+    }
+
+    resolved
+}
+
+/// Mentioned users keyed by the database id of each post or comment
+pub(super) async fn resolved(
+    state: &BlogDrownState,
+    target: Target,
+    ids: Vec<String>,
+) -> Result<HashMap<String, Vec<MinUser>>, ApiError> {
+    let mentions = state
+        .prisma
+        .mention()
+        .find_many(vec![target_filter(target, ids)])
+        .select(mention::select!({
+            post_id
+            comment_id
+            user: select { id username }
+        }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    let mut out: HashMap<String, Vec<MinUser>> = HashMap::new();
+
+    for m in mentions {
+        let Some(id) = m.post_id.or(m.comment_id) else {
+            continue;
+        };
+
+        out.entry(id).or_default().push(MinUser {
+            id: expect_uuid(&m.user.id),
+            username: BoundString::new_unchecked(m.user.username),
+        });
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_mentions_in_prose() {
+        assert_eq!(
+            parse("Thanks @ferris_crab and @ferris_crab, cc **@bob-the-builder**."),
+            ["ferris_crab", "bob-the-builder"]
+        );
+        assert_eq!(parse("Ask @someone.\nor mail me@example.com"), ["someone"]);
+        assert!(parse("@short is too short").is_empty());
+    }
+
+    #[test]
+    fn skips_code() {
+        let body = "Use `@dataclass` here\n\n```java\n@Override\n```\n\n    @indented_code\n\nbut ping @reviewer";
+
+        assert_eq!(parse(body), ["reviewer"]);
+    }
+
+    #[test]
+    fn caps_mentions() {
+        let body = (0..30)
+            .map(|i| format!("@user_{i:03}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let found = parse(&body);
+
+        assert_eq!(found.len(), MAX_MENTIONS);
+        assert_eq!(found[0], "user_000");
+    }
+}
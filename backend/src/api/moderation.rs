@@ -0,0 +1,296 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post, put},
+    Json, Router,
+};
+use chrono::Utc;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    api::{Error, MinUser},
+    audit,
+    auth::{Admin, Moderator, RequireLogin, RequireRole},
+    bounded::BoundString,
+    ip::ClientIp,
+    prisma::{AuditKind, ModerationKind},
+    BlogDrownState,
+};
+
+use super::{
+    expect_uuid, ApiError, ApiJson, ChangeRole, CommentRemoved, ModerationActionItem,
+    ModerationReason, PostRemoved, PostUpdate,
+};
+
+pub(super) async fn record_action(
+    state: &BlogDrownState,
+    moderator: &RequireLogin,
+    kind: ModerationKind,
+    target: Ulid,
+    reason: Option<String>,
+) {
+    use crate::prisma::{moderation_action, user};
+
+    let res = state
+        .prisma
+        .moderation_action()
+        .create(
+            kind,
+            Uuid::from(target).to_string(),
+            vec![
+                moderation_action::moderator::connect(user::id::equals(moderator.uuid())),
+                moderation_action::reason::set(reason),
+            ],
+        )
+        .exec()
+        .await;
+
+    if let Err(e) = res {
+        tracing::error!("failed to record moderation action {kind:?} on {target}: {e}");
+    }
+}
+
+pub(super) async fn set_post_hidden(
+    state: &BlogDrownState,
+    moderator: &RequireLogin,
+    post_id: Ulid,
+    hidden: bool,
+    reason: ModerationReason,
+) -> Result<(), ApiError> {
+    use crate::prisma::blog_post;
+
+    let updated = state
+        .prisma
+        .blog_post()
+        .update_many(
+            vec![blog_post::id::equals(Uuid::from(post_id).to_string())],
+            vec![blog_post::hidden_at::set(hidden.then(|| Utc::now().into()))],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if updated == 0 {
+        return Err(Error::not_found());
+    }
+
+    if hidden {
+        state
+            .live
+            .publish(post_id, None, PostUpdate::PostRemoved(PostRemoved {}));
+    }
+
+    let kind = if hidden {
+        ModerationKind::HidePost
+    } else {
+        ModerationKind::UnhidePost
+    };
+
+    record_action(
+        state,
+        moderator,
+        kind,
+        post_id,
+        reason.reason.map(BoundString::into_inner),
+    )
+    .await;
+
+    Ok(())
+}
+
+pub(super) async fn set_comment_hidden(
+    state: &BlogDrownState,
+    moderator: &RequireLogin,
+    comment_id: Ulid,
+    hidden: bool,
+    reason: ModerationReason,
+) -> Result<(), ApiError> {
+    use crate::prisma::comment;
+
+    let updated = state
+        .prisma
+        .comment()
+        .update_many(
+            vec![comment::id::equals(Uuid::from(comment_id).to_string())],
+            vec![comment::hidden_at::set(hidden.then(|| Utc::now().into()))],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if updated == 0 {
+        return Err(Error::not_found());
+    }
+
+    if hidden {
+        let hidden_comment = state
+            .prisma
+            .comment()
+            .find_unique(comment::id::equals(Uuid::from(comment_id).to_string()))
+            .select(comment::select!({ post_id author_id }))
+            .exec()
+            .await
+            .map_err(Error::from_query)?;
+
+        if let Some(c) = hidden_comment {
+            state.live.publish(
+                expect_uuid(&c.post_id),
+                Some(expect_uuid(&c.author_id)),
+                PostUpdate::CommentRemoved(CommentRemoved { id: comment_id }),
+            );
+        }
+    }
+
+    let kind = if hidden {
+        ModerationKind::HideComment
+    } else {
+        ModerationKind::UnhideComment
+    };
+
+    record_action(
+        state,
+        moderator,
+        kind,
+        comment_id,
+        reason.reason.map(BoundString::into_inner),
+    )
+    .await;
+
+    Ok(())
+}
+
+async fn hide_post(
+    moderator: RequireRole<Moderator>,
+    Path(post_id): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+    ApiJson(reason): ApiJson<ModerationReason>,
+) -> Result<(), ApiError> {
+    set_post_hidden(&state, &moderator.login, post_id, true, reason).await
+}
+
+async fn unhide_post(
+    moderator: RequireRole<Moderator>,
+    Path(post_id): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+    ApiJson(reason): ApiJson<ModerationReason>,
+) -> Result<(), ApiError> {
+    set_post_hidden(&state, &moderator.login, post_id, false, reason).await
+}
+
+async fn hide_comment(
+    moderator: RequireRole<Moderator>,
+    Path(comment_id): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+    ApiJson(reason): ApiJson<ModerationReason>,
+) -> Result<(), ApiError> {
+    set_comment_hidden(&state, &moderator.login, comment_id, true, reason).await
+}
+
+async fn unhide_comment(
+    moderator: RequireRole<Moderator>,
+    Path(comment_id): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+    ApiJson(reason): ApiJson<ModerationReason>,
+) -> Result<(), ApiError> {
+    set_comment_hidden(&state, &moderator.login, comment_id, false, reason).await
+}
+
+async fn change_role(
+    admin: RequireRole<Admin>,
+    ClientIp(ip): ClientIp,
+    Path(uid): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+    ApiJson(change): ApiJson<ChangeRole>,
+) -> Result<(), ApiError> {
+    use crate::prisma::user;
+
+    if uid == admin.login.id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(Error::new("You cannot change your own role")),
+        ));
+    }
+
+    let updated = state
+        .prisma
+        .user()
+        .update_many(
+            vec![user::id::equals(Uuid::from(uid).to_string())],
+            vec![user::role::set(change.role)],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if updated == 0 {
+        return Err(Error::not_found());
+    }
+
+    audit::record(
+        &state,
+        AuditKind::RoleChange,
+        Some(admin.login.id),
+        Some(uid),
+        Some(ip),
+        Some(format!("{:?}", change.role)),
+    )
+    .await;
+
+    record_action(
+        &state,
+        &admin.login,
+        ModerationKind::ChangeRole,
+        uid,
+        Some(format!("{:?}", change.role)),
+    )
+    .await;
+
+    Ok(())
+}
+
+async fn list_actions(
+    _: RequireRole<Moderator>,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<Vec<ModerationActionItem>>, ApiError> {
+    use crate::prisma::moderation_action;
+    use prisma_client_rust::Direction;
+
+    let actions = state
+        .prisma
+        .moderation_action()
+        .find_many(vec![])
+        .order_by(moderation_action::created_at::order(Direction::Desc))
+        .take(100)
+        .include(moderation_action::include!({ moderator }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(
+        actions
+            .into_iter()
+            .map(|a| ModerationActionItem {
+                id: a.id,
+                moderator: a.moderator.map(|m| MinUser {
+                    id: expect_uuid(&m.id),
+                    username: BoundString::new_unchecked(m.username),
+                }),
+                kind: a.kind,
+                target_id: expect_uuid(&a.target_id),
+                reason: a.reason,
+                created_at: a.created_at,
+            })
+            .collect(),
+    ))
+}
+
+pub fn routes() -> Router<BlogDrownState> {
+    Router::new()
+        .route("/actions", get(list_actions))
+        .route("/posts/:post_id/hide", post(hide_post))
+        .route("/posts/:post_id/unhide", post(unhide_post))
+        .route("/comments/:comment_id/hide", post(hide_comment))
+        .route("/comments/:comment_id/unhide", post(unhide_comment))
+        .route("/users/:userId/role", put(change_role))
+}
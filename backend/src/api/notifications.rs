@@ -0,0 +1,159 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+
+use crate::{
+    api::{expect_uuid, Error, MinUser},
+    auth::RequireLogin,
+    bounded::BoundString,
+    prisma::notification,
+    BlogDrownState,
+};
+
+use super::{ApiError, NotificationItem, NotificationQuery, NotificationsRead, UnreadCount};
+
+const PAGE_SIZE: i64 = 50;
+
+notification::select!(notification_item {
+    id kind post_id comment_id read_at created_at
+    actor: select { id username }
+});
+
+impl From<notification_item::Data> for NotificationItem {
+    fn from(n: notification_item::Data) -> Self {
+        Self {
+            id: n.id,
+            kind: n.kind,
+            actor: n.actor.map(|a| MinUser {
+                id: expect_uuid(&a.id),
+                username: BoundString::new_unchecked(a.username),
+            }),
+            post_id: n.post_id.as_deref().map(expect_uuid),
+            comment_id: n.comment_id.as_deref().map(expect_uuid),
+            read_at: n.read_at,
+            created_at: n.created_at,
+        }
+    }
+}
+
+async fn list_notifications(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+    Query(query): Query<NotificationQuery>,
+) -> Result<Json<Vec<NotificationItem>>, ApiError> {
+    use prisma_client_rust::Direction;
+
+    let mut filter = vec![notification::user_id::equals(auth.uuid())];
+
+    if query.unread {
+        filter.push(notification::read_at::equals(None));
+    }
+
+    let notifications = state
+        .prisma
+        .notification()
+        .find_many(filter)
+        .order_by(notification::id::order(Direction::Desc))
+        .skip(i64::from(query.page) * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .select(notification_item::select())
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(
+        notifications
+            .into_iter()
+            .map(NotificationItem::from)
+            .collect(),
+    ))
+}
+
+async fn unread_count(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<UnreadCount>, ApiError> {
+    let unread = state
+        .prisma
+        .notification()
+        .count(vec![
+            notification::user_id::equals(auth.uuid()),
+            notification::read_at::equals(None),
+        ])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(UnreadCount { unread }))
+}
+
+async fn mark_read(
+    auth: RequireLogin,
+    Path(id): Path<i64>,
+    State(state): State<BlogDrownState>,
+) -> Result<(), ApiError> {
+    // marking an already read notification again is fine, only a foreign or missing one is not
+    let found = state
+        .prisma
+        .notification()
+        .count(vec![
+            notification::id::equals(id),
+            notification::user_id::equals(auth.uuid()),
+        ])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if found == 0 {
+        return Err(Error::not_found());
+    }
+
+    state
+        .prisma
+        .notification()
+        .update_many(
+            vec![
+                notification::id::equals(id),
+                notification::user_id::equals(auth.uuid()),
+                notification::read_at::equals(None),
+            ],
+            vec![notification::read_at::set(Some(Utc::now().into()))],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(())
+}
+
+async fn mark_all_read(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<NotificationsRead>, ApiError> {
+    let read = state
+        .prisma
+        .notification()
+        .update_many(
+            vec![
+                notification::user_id::equals(auth.uuid()),
+                notification::read_at::equals(None),
+            ],
+            vec![notification::read_at::set(Some(Utc::now().into()))],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(NotificationsRead { read }))
+}
+
+pub fn routes() -> Router<BlogDrownState> {
+    Router::new()
+        .route("/", get(list_notifications))
+        .route("/unread", get(unread_count))
+        .route("/read", post(mark_all_read))
+        .route("/:id/read", post(mark_read))
+}
@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use prisma_client_rust::{prisma_errors::query_engine::UniqueKeyViolation, PrismaValue, Raw};
+use serde_derive::Deserialize;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    api::Error,
+    auth::RequireLogin,
+    prisma::{self, reaction},
+    BlogDrownState,
+};
+
+use super::{follows::is_blocked, ApiError, ApiJson, React, ReactionCount};
+
+#[derive(Clone, Copy)]
+pub(super) enum Target {
+    Post,
+    Comment,
+}
+
+fn target_filter(target: Target, ids: Vec<String>) -> reaction::WhereParam {
+    match target {
+        Target::Post => reaction::post_id::in_vec(ids),
+        Target::Comment => reaction::comment_id::in_vec(ids),
+    }
+}
+
+#[derive(Deserialize)]
+struct EmojiCount {
+    target: String,
+    emoji: String,
+    count: i64,
+}
+
+/// Aggregated reactions keyed by the database id of each post or comment,
+/// ordered like the configured reaction set
+pub(super) async fn counts(
+    state: &BlogDrownState,
+    target: Target,
+    ids: Vec<String>,
+    viewer: Option<&RequireLogin>,
+) -> Result<HashMap<String, Vec<ReactionCount>>, ApiError> {
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let column = match target {
+        Target::Post => "post_id",
+        Target::Comment => "comment_id",
+    };
+
+    let placeholders = vec!["{}::uuid"; ids.len()].join(", ");
+
+    let totals = state
+        .prisma
+        ._query_raw::<EmojiCount>(Raw::new(
+            &format!(
+                r#"SELECT "{column}"::text AS "target", "emoji", COUNT(*)::int AS "count"
+                FROM "Reaction" WHERE "{column}" IN ({placeholders})
+                GROUP BY "{column}", "emoji""#
+            ),
+            ids.iter().cloned().map(PrismaValue::String).collect(),
+        ))
+        .exec();
+
+    let own = async {
+        let Some(viewer) = viewer else {
+            return Ok(vec![]);
+        };
+
+        state
+            .prisma
+            .reaction()
+            .find_many(vec![
+                target_filter(target, ids.clone()),
+                reaction::user_id::equals(viewer.uuid()),
+            ])
+            .select(reaction::select!({ post_id comment_id emoji }))
+            .exec()
+            .await
+    };
+
+    let (totals, own) = tokio::try_join!(totals, own).map_err(Error::from_query)?;
+
+    let own = own
+        .into_iter()
+        .filter_map(|r| Some((r.post_id.or(r.comment_id)?, r.emoji)))
+        .collect::<HashSet<_>>();
+
+    let mut out: HashMap<String, Vec<ReactionCount>> = HashMap::new();
+
+    for total in totals {
+        let reacted = own.contains(&(total.target.clone(), total.emoji.clone()));
+
+        out.entry(total.target).or_default().push(ReactionCount {
+            emoji: total.emoji,
+            count: total.count,
+            reacted,
+        });
+    }
+
+    // reactions that were removed from the configured set sort last
+    let position = |emoji: &str| {
+        state
+            .reactions
+            .iter()
+            .position(|e| e == emoji)
+            .unwrap_or(usize::MAX)
+    };
+
+    for counts in out.values_mut() {
+        counts.sort_by_key(|c| position(&c.emoji));
+    }
+
+    Ok(out)
+}
+
+/// Adds the reaction if the user has not reacted with it yet, otherwise removes it
+async fn toggle(
+    state: &BlogDrownState,
+    auth: &RequireLogin,
+    target: Target,
+    id: Ulid,
+    emoji: String,
+) -> Result<Json<Vec<ReactionCount>>, ApiError> {
+    use prisma::{blog_post, comment, user};
+
+    if !state.reactions.contains(&emoji) {
+        let mut err = Error::new("Unknown reaction");
+        err.add(
+            "emoji",
+            format!("Reaction must be one of {}", state.reactions.join(" ")),
+        );
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(err)));
+    }
+
+    let id = Uuid::from(id).to_string();
+
+    let author = match target {
+        Target::Post => state
+            .prisma
+            .blog_post()
+            .find_unique(blog_post::id::equals(id.clone()))
+            .select(blog_post::select!({ owner_id hidden_at }))
+            .exec()
+            .await
+            .map_err(Error::from_query)?
+            .filter(|p| p.hidden_at.is_none())
+            .map(|p| p.owner_id),
+        Target::Comment => state
+            .prisma
+            .comment()
+            .find_unique(comment::id::equals(id.clone()))
+            .select(comment::select!({ author_id hidden_at }))
+            .exec()
+            .await
+            .map_err(Error::from_query)?
+            .filter(|c| c.hidden_at.is_none())
+            .map(|c| c.author_id),
+    }
+    .ok_or_else(Error::not_found)?;
+
+    if is_blocked(state, &author, &auth.uuid()).await? {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(Error::new("You cannot react to this user's content")),
+        ));
+    }
+
+    let removed = state
+        .prisma
+        .reaction()
+        .delete_many(vec![
+            reaction::user_id::equals(auth.uuid()),
+            reaction::emoji::equals(emoji.clone()),
+            target_filter(target, vec![id.clone()]),
+        ])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if removed == 0 {
+        let link = match target {
+            Target::Post => reaction::post::connect(blog_post::id::equals(id.clone())),
+            Target::Comment => reaction::comment::connect(comment::id::equals(id.clone())),
+        };
+
+        let created = state
+            .prisma
+            .reaction()
+            .create(user::id::equals(auth.uuid()), emoji, vec![link])
+            .exec()
+            .await;
+
+        // a concurrent toggle already added the same reaction
+        if let Err(e) = created {
+            if !e.is_prisma_error::<UniqueKeyViolation>() {
+                return Err(Error::from_query(e));
+            }
+        }
+    }
+
+    let mut summary = counts(state, target, vec![id.clone()], Some(auth)).await?;
+
+    Ok(Json(summary.remove(&id).unwrap_or_default()))
+}
+
+pub(super) async fn toggle_post(
+    auth: RequireLogin,
+    Path(post_id): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+    ApiJson(react): ApiJson<React>,
+) -> Result<Json<Vec<ReactionCount>>, ApiError> {
+    toggle(
+        &state,
+        &auth,
+        Target::Post,
+        post_id,
+        react.emoji.into_inner(),
+    )
+    .await
+}
+
+pub(super) async fn toggle_comment(
+    auth: RequireLogin,
+    Path(comment_id): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+    ApiJson(react): ApiJson<React>,
+) -> Result<Json<Vec<ReactionCount>>, ApiError> {
+    toggle(
+        &state,
+        &auth,
+        Target::Comment,
+        comment_id,
+        react.emoji.into_inner(),
+    )
+    .await
+}
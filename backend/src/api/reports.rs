@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use prisma_client_rust::or;
+use serde_derive::Deserialize;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    api::{Created, Error, MinUser},
+    auth::{self, Moderator, RequireLogin, RequireRole},
+    bounded::BoundString,
+    prisma::{self, report, ReportResolution, Role},
+    BlogDrownState,
+};
+
+use super::{
+    admin, expect_uuid,
+    moderation::{set_comment_hidden, set_post_hidden},
+    ApiError, ApiJson, ModerationReason, ReportContent, ReportGroup, ReportItem, ReportQuery,
+    ReportTarget, ReportsResolved,
+};
+
+const EXCERPT_LEN: usize = 200;
+/// Reported posts and comments per page of the queue
+const PAGE_SIZE: i64 = 50;
+
+fn target_filter(kind: ReportTarget, id: String) -> report::WhereParam {
+    match kind {
+        ReportTarget::Post => report::post_id::equals(Some(id)),
+        ReportTarget::Comment => report::comment_id::equals(Some(id)),
+    }
+}
+
+/// Looks up the author of the reported content and whether it is currently hidden
+async fn target_author(
+    state: &BlogDrownState,
+    kind: ReportTarget,
+    id: &str,
+) -> Result<Option<(Ulid, bool)>, ApiError> {
+    use prisma::{blog_post, comment};
+
+    Ok(match kind {
+        ReportTarget::Post => state
+            .prisma
+            .blog_post()
+            .find_unique(blog_post::id::equals(id.to_owned()))
+            .select(blog_post::select!({ owner_id hidden_at }))
+            .exec()
+            .await
+            .map_err(Error::from_query)?
+            .map(|p| (expect_uuid(&p.owner_id), p.hidden_at.is_some())),
+        ReportTarget::Comment => state
+            .prisma
+            .comment()
+            .find_unique(comment::id::equals(id.to_owned()))
+            .select(comment::select!({ author_id hidden_at }))
+            .exec()
+            .await
+            .map_err(Error::from_query)?
+            .map(|c| (expect_uuid(&c.author_id), c.hidden_at.is_some())),
+    })
+}
+
+async fn report_content(
+    auth: RequireLogin,
+    Path((kind, target)): Path<(ReportTarget, Ulid)>,
+    State(state): State<BlogDrownState>,
+    ApiJson(content): ApiJson<ReportContent>,
+) -> Result<Created<()>, ApiError> {
+    use prisma::{blog_post, comment, user};
+
+    let id = Uuid::from(target).to_string();
+
+    let Some((author, false)) = target_author(&state, kind, &id).await? else {
+        return Err(Error::not_found());
+    };
+
+    if author == auth.id {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(Error::new("You cannot report your own content")),
+        ));
+    }
+
+    let existing = state
+        .prisma
+        .report()
+        .count(vec![
+            target_filter(kind, id.clone()),
+            report::reporter_id::equals(Some(auth.uuid())),
+            report::resolved_at::equals(None),
+        ])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if existing != 0 {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(Error::new("You have already reported this content")),
+        ));
+    }
+
+    let target = match kind {
+        ReportTarget::Post => report::post::connect(blog_post::id::equals(id)),
+        ReportTarget::Comment => report::comment::connect(comment::id::equals(id)),
+    };
+
+    state
+        .prisma
+        .report()
+        .create(
+            content.reason.into_inner(),
+            vec![
+                report::reporter::connect(user::id::equals(auth.uuid())),
+                target,
+            ],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Created(()))
+}
+
+#[derive(Deserialize)]
+struct OpenTarget {
+    post_id: Option<String>,
+    comment_id: Option<String>,
+}
+
+/// Lists open reports grouped by the reported content, ordered by their oldest report
+async fn report_queue(
+    _: RequireRole<Moderator>,
+    State(state): State<BlogDrownState>,
+    Query(query): Query<ReportQuery>,
+) -> Result<Json<Vec<ReportGroup>>, ApiError> {
+    use prisma_client_rust::{raw, Direction, PrismaValue};
+
+    // paginated by target, so every group on the page carries all of its reports
+    let targets: Vec<OpenTarget> = state
+        .prisma
+        ._query_raw(raw!(
+            r#"SELECT "post_id"::text, "comment_id"::text FROM "Report"
+            WHERE "resolved_at" IS NULL
+            GROUP BY "post_id", "comment_id"
+            ORDER BY MIN("created_at"), "post_id", "comment_id"
+            LIMIT {} OFFSET {}"#,
+            PrismaValue::Int(PAGE_SIZE),
+            PrismaValue::Int(i64::from(query.page) * PAGE_SIZE)
+        ))
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    let (posts, comments): (Vec<_>, Vec<_>) = targets
+        .into_iter()
+        .map(|t| (t.post_id, t.comment_id))
+        .unzip();
+
+    let reports = state
+        .prisma
+        .report()
+        .find_many(vec![
+            report::resolved_at::equals(None),
+            or![
+                report::post_id::in_vec(posts.into_iter().flatten().collect()),
+                report::comment_id::in_vec(comments.into_iter().flatten().collect())
+            ],
+        ])
+        .order_by(report::created_at::order(Direction::Asc))
+        .order_by(report::id::order(Direction::Asc))
+        .select(report::select!({
+            id reason created_at
+            reporter: select { id username }
+            post: select { id title hidden_at owner: select { id username } }
+            comment: select { id text hidden_at author: select { id username } }
+        }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    let mut groups: Vec<ReportGroup> = vec![];
+    let mut index = HashMap::new();
+
+    for report in reports {
+        let (kind, target_id, author, excerpt, hidden) = match (report.post, report.comment) {
+            (Some(post), _) => (
+                ReportTarget::Post,
+                post.id,
+                post.owner,
+                post.title,
+                post.hidden_at.is_some(),
+            ),
+            (None, Some(comment)) => (
+                ReportTarget::Comment,
+                comment.id,
+                comment.author,
+                comment.text.chars().take(EXCERPT_LEN).collect(),
+                comment.hidden_at.is_some(),
+            ),
+            (None, None) => {
+                tracing::warn!("Database integrity: Report({}) has no target", report.id);
+                continue;
+            }
+        };
+
+        let item = ReportItem {
+            id: report.id,
+            reporter: report.reporter.map(|r| MinUser {
+                id: expect_uuid(&r.id),
+                username: BoundString::new_unchecked(r.username),
+            }),
+            reason: report.reason,
+            created_at: report.created_at,
+        };
+
+        let target_id = expect_uuid(&target_id);
+
+        let group = *index.entry((kind, target_id)).or_insert_with(|| {
+            groups.push(ReportGroup {
+                kind,
+                target_id,
+                author: MinUser {
+                    id: expect_uuid(&author.id),
+                    username: BoundString::new_unchecked(author.username),
+                },
+                excerpt,
+                hidden,
+                reports: vec![],
+            });
+
+            groups.len() - 1
+        });
+
+        groups[group].reports.push(item);
+    }
+
+    Ok(Json(groups))
+}
+
+async fn hide_target(
+    state: &BlogDrownState,
+    moderator: &RequireLogin,
+    kind: ReportTarget,
+    target: Ulid,
+    reason: Option<BoundString<1, 1000>>,
+) -> Result<(), ApiError> {
+    let reason = ModerationReason { reason };
+
+    match kind {
+        ReportTarget::Post => set_post_hidden(state, moderator, target, true, reason).await,
+        ReportTarget::Comment => set_comment_hidden(state, moderator, target, true, reason).await,
+    }
+}
+
+/// Applies the moderator's decision and closes every open report on the target
+async fn resolve(
+    state: &BlogDrownState,
+    moderator: &RequireRole<Moderator>,
+    kind: ReportTarget,
+    target: Ulid,
+    resolution: ReportResolution,
+    reason: ModerationReason,
+) -> Result<Json<ReportsResolved>, ApiError> {
+    use prisma::user;
+
+    let id = Uuid::from(target).to_string();
+
+    let open = || {
+        vec![
+            target_filter(kind, id.clone()),
+            report::resolved_at::equals(None),
+        ]
+    };
+
+    let count = state
+        .prisma
+        .report()
+        .count(open())
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if count == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(Error::new("No open reports for this content")),
+        ));
+    }
+
+    match resolution {
+        ReportResolution::Dismissed => {}
+        ReportResolution::ContentHidden => {
+            hide_target(state, &moderator.login, kind, target, reason.reason).await?;
+        }
+        ReportResolution::AuthorSuspended => {
+            let (author, _) = target_author(state, kind, &id)
+                .await?
+                .ok_or_else(Error::not_found)?;
+
+            if author == moderator.login.id {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(Error::new("You cannot suspend your own account")),
+                ));
+            }
+
+            let author_role = state
+                .prisma
+                .user()
+                .find_unique(user::id::equals(Uuid::from(author).to_string()))
+                .select(user::select!({ role }))
+                .exec()
+                .await
+                .map_err(Error::from_query)?
+                .ok_or_else(Error::not_found)?
+                .role;
+
+            if auth::at_least(author_role, Role::Moderator)
+                && !auth::at_least(moderator.role, Role::Admin)
+            {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    Json(Error::new("Only admins may suspend moderators")),
+                ));
+            }
+
+            hide_target(state, &moderator.login, kind, target, reason.reason.clone()).await?;
+
+            // an author that is already suspended still gets their reports closed
+            admin::suspend(
+                state,
+                &moderator.login,
+                author,
+                reason.reason.map(BoundString::into_inner),
+            )
+            .await?;
+        }
+    }
+
+    let resolved = state
+        .prisma
+        .report()
+        .update_many(
+            open(),
+            vec![
+                report::resolved_at::set(Some(Utc::now().into())),
+                report::resolved_by_id::set(Some(moderator.login.uuid())),
+                report::resolution::set(Some(resolution)),
+            ],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(ReportsResolved { resolved }))
+}
+
+async fn dismiss(
+    moderator: RequireRole<Moderator>,
+    Path((kind, target)): Path<(ReportTarget, Ulid)>,
+    State(state): State<BlogDrownState>,
+    ApiJson(reason): ApiJson<ModerationReason>,
+) -> Result<Json<ReportsResolved>, ApiError> {
+    resolve(
+        &state,
+        &moderator,
+        kind,
+        target,
+        ReportResolution::Dismissed,
+        reason,
+    )
+    .await
+}
+
+async fn hide(
+    moderator: RequireRole<Moderator>,
+    Path((kind, target)): Path<(ReportTarget, Ulid)>,
+    State(state): State<BlogDrownState>,
+    ApiJson(reason): ApiJson<ModerationReason>,
+) -> Result<Json<ReportsResolved>, ApiError> {
+    resolve(
+        &state,
+        &moderator,
+        kind,
+        target,
+        ReportResolution::ContentHidden,
+        reason,
+    )
+    .await
+}
+
+async fn suspend_author(
+    moderator: RequireRole<Moderator>,
+    Path((kind, target)): Path<(ReportTarget, Ulid)>,
+    State(state): State<BlogDrownState>,
+    ApiJson(reason): ApiJson<ModerationReason>,
+) -> Result<Json<ReportsResolved>, ApiError> {
+    resolve(
+        &state,
+        &moderator,
+        kind,
+        target,
+        ReportResolution::AuthorSuspended,
+        reason,
+    )
+    .await
+}
+
+pub fn routes() -> Router<BlogDrownState> {
+    Router::new()
+        .route("/", get(report_queue))
+        .route("/:kind/:target_id", post(report_content))
+        .route("/:kind/:target_id/dismiss", post(dismiss))
+        .route("/:kind/:target_id/hide", post(hide))
+        .route("/:kind/:target_id/suspend", post(suspend_author))
+}
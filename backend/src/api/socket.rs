@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::{
+        header::{HOST, ORIGIN},
+        HeaderMap, StatusCode,
+    },
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use chrono::Utc;
+use tokio::{sync::broadcast::error::RecvError, time::Instant};
+use ulid::Ulid;
+
+use crate::{api::Error, auth::RequireLogin, BlogDrownState};
+
+use super::{ApiError, SocketEvent, SocketQuery, UserMessage};
+
+/// How often the server pings, clients silent for three intervals are dropped
+const HEARTBEAT: Duration = Duration::from_secs(30);
+
+async fn send(socket: &mut WebSocket, event_id: Option<Ulid>, message: &UserMessage) -> bool {
+    let text = serde_json::to_string(&SocketEvent { event_id, message })
+        .expect("SocketEvent serializes to json");
+
+    socket.send(Message::Text(text)).await.is_ok()
+}
+
+async fn run(
+    mut socket: WebSocket,
+    state: BlogDrownState,
+    auth: RequireLogin,
+    last_seen: Option<Ulid>,
+) {
+    let user = auth.id;
+    let mut subscription = state.live.follow_user(user, last_seen);
+
+    // revocations are checked with each heartbeat, the expiry is exact
+    let expiry = tokio::time::sleep(
+        (auth.expires_at() - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    );
+    tokio::pin!(expiry);
+
+    if subscription.resync && !send(&mut socket, None, &UserMessage::Resync).await {
+        return;
+    }
+
+    for event in subscription.missed {
+        if !send(&mut socket, Some(event.id), &event.message).await {
+            return;
+        }
+    }
+
+    let mut heartbeat = tokio::time::interval(HEARTBEAT);
+    let mut last_heard = Instant::now();
+
+    loop {
+        tokio::select! {
+            () = &mut expiry => break,
+            _ = heartbeat.tick() => {
+                if last_heard.elapsed() > HEARTBEAT * 3 {
+                    break;
+                }
+
+                match auth.revoked(&state).await {
+                    Ok(None) => {}
+                    Ok(Some(_)) => {
+                        _ = send(&mut socket, None, &UserMessage::SessionRevoked).await;
+                        break;
+                    }
+                    // a database hiccup should not drop every socket
+                    Err(e) => tracing::warn!("failed to check the session of User({user}): {e}"),
+                }
+
+                if socket.send(Message::Ping(vec![])).await.is_err() {
+                    break;
+                }
+            }
+            received = socket.recv() => match received {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                // pongs and anything else the client sends count as signs of life
+                Some(Ok(_)) => last_heard = Instant::now(),
+            },
+            event = subscription.events.recv() => match event {
+                Ok(event) if event.users.contains(&user) => {
+                    if !send(&mut socket, Some(event.id), &event.message).await {
+                        break;
+                    }
+
+                    if matches!(event.message, UserMessage::SessionRevoked) {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => {
+                    if !send(&mut socket, None, &UserMessage::Resync).await {
+                        break;
+                    }
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+
+    _ = socket.send(Message::Close(None)).await;
+}
+
+/// Browsers send cookies along with cross site upgrades, which are GET requests and so
+/// are not checked for CSRF, only our own pages and the configured origins may connect
+fn allowed_origin(state: &BlogDrownState, headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(ORIGIN) else {
+        // only browsers send an origin and they always do
+        return true;
+    };
+
+    if !state.production || state.cors_origins.contains(origin) {
+        return true;
+    }
+
+    let host = state
+        .trust_proxy
+        .then(|| headers.get("x-forwarded-host"))
+        .flatten()
+        .or_else(|| headers.get(HOST))
+        .and_then(|h| h.to_str().ok());
+
+    origin
+        .to_str()
+        .ok()
+        .and_then(|o| o.split_once("://"))
+        .is_some_and(|(_, origin_host)| Some(origin_host) == host)
+}
+
+async fn connect(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+    Query(query): Query<SocketQuery>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    if !allowed_origin(&state, &headers) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(Error::new("Connecting from this origin is not allowed")),
+        ));
+    }
+
+    Ok(upgrade.on_upgrade(move |socket| run(socket, state, auth, query.last_event_id)))
+}
+
+pub fn routes() -> Router<BlogDrownState> {
+    Router::new().route("/", get(connect))
+}
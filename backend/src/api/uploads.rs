@@ -0,0 +1,586 @@
+use axum::{
+    body::Bytes,
+    extract::{multipart::MultipartRejection, DefaultBodyLimit, Multipart, Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use image::ImageError;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    api::{expect_uuid, Created, Error},
+    auth::{self, RequireLogin},
+    images,
+    prisma::{upload, upload_variant, user, PrismaClient, Role, UploadVariantKind},
+    ratelimit::{Throttle, UploadAttempts},
+    storage, BlogDrownState,
+};
+
+use super::{ApiError, UploadItem, UploadVariantItem, Uploads};
+
+/// File types we store by extension, the type of an upload is always sniffed from its contents
+const KINDS: [(&str, &str); 6] = [
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("pdf", "application/pdf"),
+];
+
+/// Uploads never change once stored, so they may be cached for as long as browsers allow
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Variant urls stay the same while the upload behind them can be deleted,
+/// so they are only cached briefly
+const VARIANT_CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// The extension of the file type the contents start with, if it is one we accept.
+///
+/// AVIF is only produced by us, it is not accepted as we cannot decode it to strip its metadata
+fn sniff(data: &[u8]) -> Option<&'static str> {
+    let ext = match data {
+        [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => "png",
+        [0xff, 0xd8, 0xff, ..] => "jpg",
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => "gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "webp",
+        [b'%', b'P', b'D', b'F', b'-', ..] => "pdf",
+        _ => return None,
+    };
+
+    Some(ext)
+}
+
+fn variant_name(kind: UploadVariantKind) -> &'static str {
+    match kind {
+        UploadVariantKind::Thumbnail => "thumbnail",
+        UploadVariantKind::Medium => "medium",
+    }
+}
+
+fn content_type(key: &str) -> Option<&'static str> {
+    let (_, ext) = key.rsplit_once('.')?;
+
+    KINDS
+        .iter()
+        .find(|(known, _)| *known == ext)
+        .map(|(_, mime)| *mime)
+}
+
+fn upload_item(upload: upload::Data) -> UploadItem {
+    let id = expect_uuid(&upload.id);
+
+    UploadItem {
+        id,
+        url: format!("/media/{}", upload.key),
+        content_type: upload.content_type,
+        size: upload.size,
+        name: upload.name,
+        width: upload.width,
+        height: upload.height,
+        variants: upload
+            .variants
+            .unwrap_or_default()
+            .into_iter()
+            .map(|v| UploadVariantItem {
+                kind: v.kind,
+                url: format!("/media/{id}/{}", variant_name(v.kind)),
+                content_type: v.content_type,
+                size: v.size,
+                width: v.width,
+                height: v.height,
+            })
+            .collect(),
+        created_at: upload.created_at,
+    }
+}
+
+fn storage_error(e: std::io::Error) -> ApiError {
+    tracing::error!("storage failure: {e}");
+
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(Error::new("Could not access file storage")),
+    )
+}
+
+/// Bytes used by all of the user's uploads and their variants
+async fn used(prisma: &PrismaClient, owner: String) -> Result<i64, ApiError> {
+    let sizes = prisma
+        .upload()
+        .find_many(vec![upload::owner_id::equals(owner)])
+        .select(upload::select!({ size variants: select { size } }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(sizes
+        .iter()
+        .flat_map(|u| std::iter::once(u.size).chain(u.variants.iter().map(|v| v.size)))
+        .map(i64::from)
+        .sum())
+}
+
+/// Removes stored files whose rows are already gone, failures are only logged
+pub async fn remove_stored(state: &BlogDrownState, keys: Vec<String>) {
+    for key in keys {
+        if let Err(e) = state.storage.delete(&key).await {
+            tracing::error!("failed to remove stored file {key}: {e}");
+        }
+    }
+}
+
+/// Reads the `file` field, other fields are skipped
+async fn read_file(
+    state: &BlogDrownState,
+    mut multipart: Multipart,
+) -> Result<(Option<String>, Vec<u8>), ApiError> {
+    let bad_request = |e: axum::extract::multipart::MultipartError| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(Error::new(format!("Invalid upload: {}", e.body_text()))),
+        )
+    };
+
+    while let Some(mut field) = multipart.next_field().await.map_err(bad_request)? {
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let name = field
+            .file_name()
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(|n| n.chars().take(255).collect());
+
+        let mut data = vec![];
+
+        while let Some(chunk) = field.chunk().await.map_err(bad_request)? {
+            if data.len() + chunk.len() > state.upload_limit {
+                return Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    Json(Error::new(format!(
+                        "Files may not be larger than {} bytes",
+                        state.upload_limit
+                    ))),
+                ));
+            }
+
+            data.extend_from_slice(&chunk);
+        }
+
+        return Ok((name, data));
+    }
+
+    let mut err = Error::new("Invalid upload");
+    err.add("file", "Missing");
+
+    Err((StatusCode::BAD_REQUEST, Json(err)))
+}
+
+/// A file to be written to storage
+struct Stored {
+    key: String,
+    data: Bytes,
+    /// Width and height of images
+    dimensions: Option<(u32, u32)>,
+    /// Unset for the upload itself
+    kind: Option<UploadVariantKind>,
+}
+
+/// Images are stored re-encoded without their metadata, along with their resized variants
+async fn prepare(
+    state: &BlogDrownState,
+    base: Ulid,
+    ext: &'static str,
+    data: Vec<u8>,
+) -> Result<Vec<Stored>, ApiError> {
+    let invalid = |e: ImageError| {
+        let mut err = Error::new("Invalid upload");
+        err.add("file", format!("Could not read the image: {e}"));
+
+        (StatusCode::BAD_REQUEST, Json(err))
+    };
+
+    if ext == "gif" {
+        let stripped = images::strip_gif(&data).map_err(invalid)?;
+
+        return Ok(vec![Stored {
+            key: format!("{base}.{ext}"),
+            data: stripped.data.into(),
+            dimensions: Some((stripped.width, stripped.height)),
+            kind: None,
+        }]);
+    }
+
+    if !images::processable(ext) {
+        return Ok(vec![Stored {
+            key: format!("{base}.{ext}"),
+            data: data.into(),
+            dimensions: None,
+            kind: None,
+        }]);
+    }
+
+    let permit = state
+        .image_permits
+        .clone()
+        .acquire_owned()
+        .await
+        .expect("image permits are never closed");
+
+    let processed = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        images::process(&data)
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("image processing panicked: {e}");
+
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(Error::new("Could not process the image")),
+        )
+    })?
+    .map_err(invalid)?;
+
+    let original = processed.original;
+
+    let mut files = vec![Stored {
+        key: format!("{base}.{}", original.ext),
+        data: original.data.into(),
+        dimensions: Some((original.width, original.height)),
+        kind: None,
+    }];
+
+    for (kind, variant) in processed.variants {
+        files.push(Stored {
+            key: format!("{base}-{}.{}", variant_name(kind), variant.ext),
+            data: variant.data.into(),
+            dimensions: Some((variant.width, variant.height)),
+            kind: Some(kind),
+        });
+    }
+
+    Ok(files)
+}
+
+/// Throttled as processing an image keeps a core busy for a while
+async fn upload_file(
+    _: Throttle<UploadAttempts>,
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Result<Created<Json<UploadItem>>, ApiError> {
+    let multipart = multipart.map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(Error::new(format!("Invalid upload: {}", e.body_text()))),
+        )
+    })?;
+
+    let (name, data) = read_file(&state, multipart).await?;
+
+    let Some(ext) = sniff(&data) else {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Json(Error::new(
+                "Only PNG, JPEG, GIF and WebP images or PDF documents may be uploaded",
+            )),
+        ));
+    };
+
+    let id = Uuid::now_v7();
+    let files = prepare(&state, Ulid::from(id), ext, data).await?;
+
+    let saved = save(&state, &auth, id, name, &files).await;
+
+    if let Err(e) = saved {
+        remove_stored(&state, files.into_iter().map(|f| f.key).collect()).await;
+        return Err(e);
+    }
+
+    let upload = state
+        .prisma
+        .upload()
+        .find_unique(upload::id::equals(id.to_string()))
+        .with(upload::variants::fetch(vec![]))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    Ok(Created::json(upload_item(upload)))
+}
+
+/// Writes the files and records them, the first being the upload itself.
+///
+/// The quota is checked with the owner's row locked, so concurrent uploads cannot both fit
+async fn save(
+    state: &BlogDrownState,
+    auth: &RequireLogin,
+    id: Uuid,
+    name: Option<String>,
+    files: &[Stored],
+) -> Result<(), ApiError> {
+    for file in files {
+        state
+            .storage
+            .put(&file.key, file.data.clone())
+            .await
+            .map_err(storage_error)?;
+    }
+
+    use prisma_client_rust::{raw, PrismaValue};
+
+    let (upload, variants) = files
+        .split_first()
+        .expect("an upload has at least one file");
+
+    let total: usize = files.iter().map(|f| f.data.len()).sum();
+
+    let mut params = vec![upload::name::set(name)];
+
+    if let Some((width, height)) = upload.dimensions {
+        params.push(upload::width::set(Some(width as i32)));
+        params.push(upload::height::set(Some(height as i32)));
+    }
+
+    let tx = state
+        .prisma
+        ._transaction()
+        .begin()
+        .await
+        .map_err(Error::from_query)?;
+
+    // held until the transaction ends, other uploads by the same user wait here
+    tx.1._execute_raw(raw!(
+        r#"SELECT 1 FROM "User" WHERE "id" = {}::uuid FOR UPDATE"#,
+        PrismaValue::String(auth.uuid())
+    ))
+    .exec()
+    .await
+    .map_err(Error::from_query)?;
+
+    if used(&tx.1, auth.uuid()).await? + total as i64 > state.upload_quota {
+        tx.0.rollback(tx.1).await.map_err(Error::from_query)?;
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(Error::new(format!(
+                "Your uploads cannot take up more than {} bytes",
+                state.upload_quota
+            ))),
+        ));
+    }
+
+    tx.1.upload()
+        .create(
+            id.to_string(),
+            user::id::equals(auth.uuid()),
+            upload.key.clone(),
+            content_type(&upload.key)
+                .expect("stored extensions have a content type")
+                .to_owned(),
+            upload.data.len() as i32,
+            params,
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    tx.1.upload_variant()
+        .create_many(
+            variants
+                .iter()
+                .filter_map(|file| {
+                    let (width, height) = file.dimensions?;
+
+                    Some(upload_variant::create_unchecked(
+                        id.to_string(),
+                        file.kind?,
+                        file.key.clone(),
+                        content_type(&file.key)
+                            .expect("stored extensions have a content type")
+                            .to_owned(),
+                        file.data.len() as i32,
+                        width as i32,
+                        height as i32,
+                        vec![],
+                    ))
+                })
+                .collect(),
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    tx.0.commit(tx.1).await.map_err(Error::from_query)?;
+
+    Ok(())
+}
+
+async fn list_uploads(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<Uploads>, ApiError> {
+    use prisma_client_rust::Direction;
+
+    let uploads = state
+        .prisma
+        .upload()
+        .find_many(vec![upload::owner_id::equals(auth.uuid())])
+        .order_by(upload::created_at::order(Direction::Desc))
+        .with(upload::variants::fetch(vec![]))
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(Uploads {
+        used: uploads
+            .iter()
+            .flat_map(|u| {
+                std::iter::once(u.size).chain(u.variants.iter().flatten().map(|v| v.size))
+            })
+            .map(i64::from)
+            .sum(),
+        quota: state.upload_quota,
+        uploads: uploads.into_iter().map(upload_item).collect(),
+    }))
+}
+
+/// Owners delete their own uploads, moderators any
+async fn delete_upload(
+    auth: RequireLogin,
+    Path(id): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+) -> Result<(), ApiError> {
+    let upload = state
+        .prisma
+        .upload()
+        .find_unique(upload::id::equals(Uuid::from(id).to_string()))
+        .select(upload::select!({ owner_id key variants: select { key } }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    if upload.owner_id != auth.uuid() && !auth::at_least(auth.role(&state).await?, Role::Moderator)
+    {
+        return Err(Error::not_found());
+    }
+
+    let deleted = state
+        .prisma
+        .upload()
+        .delete_many(vec![upload::id::equals(Uuid::from(id).to_string())])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if deleted == 0 {
+        return Err(Error::not_found());
+    }
+
+    let mut keys = vec![upload.key];
+    keys.extend(upload.variants.into_iter().map(|v| v.key));
+
+    remove_stored(&state, keys).await;
+
+    Ok(())
+}
+
+async fn serve(
+    state: &BlogDrownState,
+    key: &str,
+    cache_control: &'static str,
+) -> Result<Response, ApiError> {
+    let Some(content_type) = content_type(key).filter(|_| storage::valid_key(key)) else {
+        return Err(Error::not_found());
+    };
+
+    let data = state
+        .storage
+        .get(key)
+        .await
+        .map_err(storage_error)?
+        .ok_or_else(Error::not_found)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CACHE_CONTROL, cache_control),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+            // documents opened directly must not run anything on our origin
+            (
+                header::CONTENT_SECURITY_POLICY,
+                "default-src 'none'; sandbox",
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+async fn serve_media(
+    Path(key): Path<String>,
+    State(state): State<BlogDrownState>,
+) -> Result<Response, ApiError> {
+    serve(&state, &key, CACHE_CONTROL).await
+}
+
+/// Serves the variant of an upload by a url that does not depend on the format it was stored in,
+/// falling back to the next larger one for images too small to have it
+async fn serve_variant(
+    Path((id, variant)): Path<(String, String)>,
+    State(state): State<BlogDrownState>,
+) -> Result<Response, ApiError> {
+    use UploadVariantKind::{Medium, Thumbnail};
+
+    let id: Ulid = id.parse().map_err(|_| Error::not_found())?;
+
+    let preferred: &[UploadVariantKind] = match variant.as_str() {
+        "thumbnail" => &[Thumbnail, Medium],
+        "medium" => &[Medium],
+        "original" => &[],
+        _ => return Err(Error::not_found()),
+    };
+
+    let upload = state
+        .prisma
+        .upload()
+        .find_unique(upload::id::equals(Uuid::from(id).to_string()))
+        .select(upload::select!({ key variants: select { kind key } }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    let key = preferred
+        .iter()
+        .find_map(|kind| upload.variants.iter().find(|v| v.kind == *kind))
+        .map_or(&upload.key, |v| &v.key);
+
+    serve(&state, key, VARIANT_CACHE_CONTROL).await
+}
+
+pub fn routes() -> Router<BlogDrownState> {
+    Router::new()
+        // the upload limit is enforced while reading, as it is only known at runtime
+        .route(
+            "/",
+            get(list_uploads)
+                .post(upload_file)
+                .layer(DefaultBodyLimit::disable()),
+        )
+        .route("/:uploadId", delete(delete_upload))
+}
+
+/// Serves stored uploads, nested at `/media`
+pub fn media_routes() -> Router<BlogDrownState> {
+    Router::new()
+        .route("/:key", get(serve_media))
+        .route("/:key/:variant", get(serve_variant))
+}
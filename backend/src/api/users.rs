@@ -0,0 +1,36 @@
+use axum::{
+    extract::{Path, State},
+    routing::get,
+    Json, Router,
+};
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{api::Error, BlogDrownState};
+
+use super::{ApiError, UserProfile};
+
+async fn get_profile(
+    Path(uid): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<UserProfile>, ApiError> {
+    use crate::prisma::user;
+
+    let user = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(Uuid::from(uid).to_string()))
+        .select(user::select!({
+            id username created_at display_name bio avatar_url website
+        }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    Ok(Json(user_profile!(user)))
+}
+
+pub fn routes() -> Router<BlogDrownState> {
+    Router::new().route("/:userId", get(get_profile))
+}
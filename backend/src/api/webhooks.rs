@@ -0,0 +1,742 @@
+use std::{net::IpAddr, time::Duration};
+
+use axum::{
+    extract::{Path, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use prisma_client_rust::or;
+use rand::Rng;
+use reqwest::Url;
+use sha2::Sha384;
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    api::{expect_uuid, Created, Error, MinUser},
+    auth::{self, RequireLogin},
+    bounded::BoundString,
+    events::Event,
+    ip,
+    prisma::{self, webhook, webhook_delivery, Role, WebhookEvent},
+    ratelimit::{Throttle, WebhookTests},
+    BlogDrownState,
+};
+
+use super::{
+    ApiError, ApiJson, NewWebhook, WebhookComment, WebhookCreated, WebhookDeliveryItem,
+    WebhookItem, WebhookPayload, WebhookPost,
+};
+
+/// Webhooks each user may register for their own posts
+const MAX_WEBHOOKS: i64 = 10;
+
+/// Waits before each retry of a failed delivery
+const BACKOFF: [Duration; 4] = [
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(30 * 60),
+];
+
+const DELIVERY_LOG: i64 = 50;
+
+/// Deliveries younger than this may still be retrying and are never pruned
+const RETRY_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+fn event_name(event: WebhookEvent) -> &'static str {
+    match event {
+        WebhookEvent::PostCreated => "PostCreated",
+        WebhookEvent::PostUpdated => "PostUpdated",
+        WebhookEvent::CommentCreated => "CommentCreated",
+        WebhookEvent::Ping => "Ping",
+    }
+}
+
+fn webhook_item(hook: webhook::Data) -> WebhookItem {
+    WebhookItem {
+        id: expect_uuid(&hook.id),
+        url: hook.url,
+        events: hook.events,
+        site_wide: hook.owner_id.is_none(),
+        created_at: hook.created_at,
+    }
+}
+
+fn delivery_item(delivery: webhook_delivery::Data) -> WebhookDeliveryItem {
+    WebhookDeliveryItem {
+        id: delivery.id,
+        event: delivery.event,
+        payload: delivery.payload,
+        attempts: delivery.attempts,
+        status_code: delivery.status_code,
+        error: delivery.error,
+        delivered_at: delivery.delivered_at,
+        created_at: delivery.created_at,
+    }
+}
+
+/// `sha384=` followed by the hex HMAC of `{timestamp}.{payload}` keyed with the webhook secret
+fn sign(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha384>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload.as_bytes());
+
+    format!("sha384={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Local addresses are only allowed in development, so tests can use a local listener
+fn check_url(state: &BlogDrownState, url: &str) -> Result<Url, &'static str> {
+    let url = Url::parse(url).map_err(|_| "Not a valid URL")?;
+
+    match url.scheme() {
+        "https" => {}
+        "http" if !state.production => {}
+        _ => return Err("Webhook URLs must use https"),
+    }
+
+    let Some(host) = url.host_str() else {
+        return Err("Webhook URLs must have a host");
+    };
+
+    // hostnames are checked when resolving, literal addresses never are
+    let literal = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>();
+
+    if state.production && literal.is_ok_and(|addr| !ip::is_public(addr)) {
+        return Err("Webhook URLs must not point at a private address");
+    }
+
+    Ok(url)
+}
+
+struct Attempt {
+    status: Option<u16>,
+    error: Option<String>,
+}
+
+/// The first attempt goes out right away, each retry after its backoff
+fn waits() -> impl Iterator<Item = Duration> {
+    [Duration::ZERO].into_iter().chain(BACKOFF)
+}
+
+async fn attempt(
+    state: &BlogDrownState,
+    hook: &webhook::Data,
+    delivery_id: i64,
+    event: WebhookEvent,
+    payload: &str,
+) -> Attempt {
+    match check_url(state, &hook.url) {
+        Ok(url) => send(&state.http, url, &hook.secret, delivery_id, event, payload).await,
+        Err(e) => Attempt {
+            status: None,
+            error: Some(e.to_owned()),
+        },
+    }
+}
+
+async fn send(
+    http: &reqwest::Client,
+    url: Url,
+    secret: &str,
+    delivery_id: i64,
+    event: WebhookEvent,
+    payload: &str,
+) -> Attempt {
+    let timestamp = Utc::now().timestamp();
+
+    let res = http
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .header("x-blogdrown-event", event_name(event))
+        .header("x-blogdrown-delivery", delivery_id)
+        .header("x-blogdrown-timestamp", timestamp)
+        .header("x-blogdrown-signature", sign(secret, timestamp, payload))
+        .body(payload.to_owned())
+        .send()
+        .await;
+
+    match res {
+        Ok(res) if res.status().is_success() => Attempt {
+            status: Some(res.status().as_u16()),
+            error: None,
+        },
+        Ok(res) => Attempt {
+            status: Some(res.status().as_u16()),
+            error: Some(format!("Responded with {}", res.status())),
+        },
+        Err(e) => {
+            // the innermost error says what actually went wrong, e.g. a refused connection
+            let mut cause: &dyn std::error::Error = &e;
+            while let Some(source) = cause.source() {
+                cause = source;
+            }
+
+            Attempt {
+                status: None,
+                error: Some(cause.to_string()),
+            }
+        }
+    }
+}
+
+/// Sends the delivery and records the outcome on its log entry
+async fn attempt_and_log(
+    state: &BlogDrownState,
+    hook: &webhook::Data,
+    delivery_id: i64,
+    event: WebhookEvent,
+    payload: &str,
+) -> Result<webhook_delivery::Data, prisma_client_rust::QueryError> {
+    let Attempt { status, error } = attempt(state, hook, delivery_id, event, payload).await;
+
+    let delivered_at = error.is_none().then(|| Utc::now().into());
+
+    state
+        .prisma
+        .webhook_delivery()
+        .update(
+            webhook_delivery::id::equals(delivery_id),
+            vec![
+                webhook_delivery::attempts::increment(1),
+                webhook_delivery::status_code::set(status.map(i32::from)),
+                webhook_delivery::error::set(error),
+                webhook_delivery::delivered_at::set(delivered_at),
+            ],
+        )
+        .exec()
+        .await
+}
+
+async fn log_delivery(
+    state: &BlogDrownState,
+    hook: &webhook::Data,
+    event: WebhookEvent,
+    payload: &str,
+) -> Result<webhook_delivery::Data, prisma_client_rust::QueryError> {
+    state
+        .prisma
+        .webhook_delivery()
+        .create(
+            webhook::id::equals(hook.id.clone()),
+            event,
+            payload.to_owned(),
+            vec![],
+        )
+        .exec()
+        .await
+}
+
+/// Delivers the payload, retrying with backoff until it succeeds or the retries run out
+async fn deliver(state: BlogDrownState, hook: webhook::Data, event: WebhookEvent, payload: String) {
+    let delivery = match log_delivery(&state, &hook, event, &payload).await {
+        Ok(delivery) => delivery,
+        Err(e) => {
+            tracing::error!("failed to log delivery for Webhook({}): {e}", hook.id);
+            return;
+        }
+    };
+
+    for wait in waits() {
+        tokio::time::sleep(wait).await;
+
+        match attempt_and_log(&state, &hook, delivery.id, event, &payload).await {
+            Ok(logged) if logged.delivered_at.is_some() => return,
+            Ok(_) => {}
+            // the webhook was deleted in the meantime
+            Err(e) => {
+                tracing::warn!(
+                    "stopping delivery {} to Webhook({}): {e}",
+                    delivery.id,
+                    hook.id
+                );
+                return;
+            }
+        }
+    }
+}
+
+/// Hourly drops the deliveries of each webhook beyond the newest `DELIVERY_LOG`
+/// once they are done retrying, so a busy webhook cannot grow the table without bound
+pub async fn prune_deliveries(state: BlogDrownState) {
+    use prisma_client_rust::{raw, PrismaValue};
+
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+
+    loop {
+        interval.tick().await;
+
+        let cutoff = Utc::now() - RETRY_WINDOW;
+
+        let pruned = state
+            .prisma
+            ._execute_raw(raw!(
+                r#"DELETE FROM "WebhookDelivery" AS d
+                WHERE d."created_at" < {}::timestamp
+                AND d."id" NOT IN (
+                    SELECT "id" FROM "WebhookDelivery"
+                    WHERE "webhook_id" = d."webhook_id"
+                    ORDER BY "id" DESC
+                    LIMIT {}
+                )"#,
+                // stored without a time zone, in UTC
+                PrismaValue::String(cutoff.naive_utc().to_string()),
+                PrismaValue::Int(DELIVERY_LOG)
+            ))
+            .exec()
+            .await;
+
+        match pruned {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("pruned {n} old webhook deliveries"),
+            Err(e) => tracing::error!("failed to prune webhook deliveries: {e}"),
+        }
+    }
+}
+
+/// Sends the event to the webhooks subscribed to it in the background,
+/// failures are logged and never reach the publisher
+pub async fn dispatch_webhooks(state: &BlogDrownState, event: &Event) {
+    use prisma::{blog_post, comment, user};
+
+    let (kind, post_id, owner, actor, comment_id) = match *event {
+        Event::PostCreated { post_id, author } => {
+            (WebhookEvent::PostCreated, post_id, author, author, None)
+        }
+        Event::PostUpdated { post_id, author } => {
+            (WebhookEvent::PostUpdated, post_id, author, author, None)
+        }
+        Event::CommentCreated {
+            post_id,
+            comment_id,
+            author,
+            post_owner,
+            ..
+        } => (
+            WebhookEvent::CommentCreated,
+            post_id,
+            post_owner,
+            author,
+            Some(comment_id),
+        ),
+        _ => return,
+    };
+
+    let uuid = |id: Ulid| Uuid::from(id).to_string();
+
+    let hooks = state
+        .prisma
+        .webhook()
+        .find_many(vec![
+            webhook::events::has(kind),
+            or![
+                webhook::owner_id::equals(Some(uuid(owner))),
+                webhook::owner_id::equals(None)
+            ],
+        ])
+        .exec()
+        .await;
+
+    let hooks = match hooks {
+        Ok(hooks) if hooks.is_empty() => return,
+        Ok(hooks) => hooks,
+        Err(e) => {
+            tracing::error!("failed to look up webhooks: {e}");
+            return;
+        }
+    };
+
+    let details = tokio::try_join!(
+        state
+            .prisma
+            .blog_post()
+            .find_unique(blog_post::id::equals(uuid(post_id)))
+            .select(blog_post::select!({ title title_norm }))
+            .exec(),
+        state
+            .prisma
+            .user()
+            .find_unique(user::id::equals(uuid(actor)))
+            .select(user::select!({ username }))
+            .exec(),
+        state
+            .prisma
+            .comment()
+            .find_many(vec![comment::id::in_vec(
+                comment_id.into_iter().map(uuid).collect()
+            )])
+            .select(comment::select!({ text }))
+            .exec(),
+    );
+
+    let (post, actor_name, comment) = match details {
+        Ok((Some(post), actor_name, mut comment)) => (post, actor_name, comment.pop()),
+        // deleted before the webhooks went out
+        Ok((None, ..)) => return,
+        Err(e) => {
+            tracing::error!("failed to build webhook payload: {e}");
+            return;
+        }
+    };
+
+    let payload = WebhookPayload {
+        event: kind,
+        actor: actor_name.map(|a| MinUser {
+            id: actor,
+            username: BoundString::new_unchecked(a.username),
+        }),
+        post: Some(WebhookPost {
+            id: post_id,
+            title: post.title,
+            title_norm: post.title_norm,
+        }),
+        comment: comment_id
+            .zip(comment)
+            .map(|(id, c)| WebhookComment { id, body: c.text }),
+        occurred_at: Utc::now().into(),
+    };
+
+    let payload = serde_json::to_string(&payload).expect("WebhookPayload serializes to json");
+
+    for hook in hooks {
+        tokio::spawn(deliver(state.clone(), hook, kind, payload.clone()));
+    }
+}
+
+/// The webhook if the user may manage it, owners manage their own and admins the site-wide ones
+async fn managed(
+    state: &BlogDrownState,
+    auth: &RequireLogin,
+    id: Ulid,
+) -> Result<webhook::Data, ApiError> {
+    let hook = state
+        .prisma
+        .webhook()
+        .find_unique(webhook::id::equals(Uuid::from(id).to_string()))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    let allowed = match &hook.owner_id {
+        Some(owner) => *owner == auth.uuid(),
+        None => auth::at_least(auth.role(state).await?, Role::Admin),
+    };
+
+    if !allowed {
+        return Err(Error::not_found());
+    }
+
+    Ok(hook)
+}
+
+async fn create_webhook(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+    ApiJson(new): ApiJson<NewWebhook>,
+) -> Result<Created<Json<WebhookCreated>>, ApiError> {
+    use prisma::user;
+
+    let invalid = |field: &str, reason: &str| {
+        let mut err = Error::new("Invalid webhook");
+        err.add(field, reason);
+        (StatusCode::BAD_REQUEST, Json(err))
+    };
+
+    if new.events.is_empty() || new.events.contains(&WebhookEvent::Ping) {
+        return Err(invalid(
+            "events",
+            "Subscribe to at least one of PostCreated, PostUpdated or CommentCreated",
+        ));
+    }
+
+    let url = check_url(&state, &new.url).map_err(|e| invalid("url", e))?;
+
+    if new.site_wide {
+        if !auth::at_least(auth.role(&state).await?, Role::Admin) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(Error::new("Only admins may create site-wide webhooks")),
+            ));
+        }
+    } else {
+        let count = state
+            .prisma
+            .webhook()
+            .count(vec![webhook::owner_id::equals(Some(auth.uuid()))])
+            .exec()
+            .await
+            .map_err(Error::from_query)?;
+
+        if count >= MAX_WEBHOOKS {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(Error::new(format!(
+                    "You cannot have more than {MAX_WEBHOOKS} webhooks"
+                ))),
+            ));
+        }
+    }
+
+    let mut events = vec![];
+    for event in new.events {
+        if !events.contains(&event) {
+            events.push(event);
+        }
+    }
+
+    let secret = hex::encode(rand::thread_rng().gen::<[u8; 32]>());
+
+    let mut params = vec![webhook::events::set(events)];
+
+    if !new.site_wide {
+        params.push(webhook::owner::connect(user::id::equals(auth.uuid())));
+    }
+
+    let hook = state
+        .prisma
+        .webhook()
+        .create(
+            Uuid::now_v7().to_string(),
+            url.to_string(),
+            secret.clone(),
+            params,
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Created::json(WebhookCreated {
+        webhook: webhook_item(hook),
+        secret,
+    }))
+}
+
+async fn list_webhooks(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<Vec<WebhookItem>>, ApiError> {
+    use prisma_client_rust::Direction;
+
+    let mut filter = vec![webhook::owner_id::equals(Some(auth.uuid()))];
+
+    if auth::at_least(auth.role(&state).await?, Role::Admin) {
+        filter = vec![or![
+            webhook::owner_id::equals(Some(auth.uuid())),
+            webhook::owner_id::equals(None)
+        ]];
+    }
+
+    let hooks = state
+        .prisma
+        .webhook()
+        .find_many(filter)
+        .order_by(webhook::created_at::order(Direction::Desc))
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(hooks.into_iter().map(webhook_item).collect()))
+}
+
+async fn delete_webhook(
+    auth: RequireLogin,
+    Path(id): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+) -> Result<(), ApiError> {
+    let hook = managed(&state, &auth, id).await?;
+
+    state
+        .prisma
+        .webhook()
+        .delete_many(vec![webhook::id::equals(hook.id)])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(())
+}
+
+async fn list_deliveries(
+    auth: RequireLogin,
+    Path(id): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<Vec<WebhookDeliveryItem>>, ApiError> {
+    use prisma_client_rust::Direction;
+
+    let hook = managed(&state, &auth, id).await?;
+
+    let deliveries = state
+        .prisma
+        .webhook_delivery()
+        .find_many(vec![webhook_delivery::webhook_id::equals(hook.id)])
+        .order_by(webhook_delivery::id::order(Direction::Desc))
+        .take(DELIVERY_LOG)
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(deliveries.into_iter().map(delivery_item).collect()))
+}
+
+/// Sends a `Ping` once without retrying and returns how it went,
+/// throttled since each one may hold the request for the whole delivery timeout
+async fn test_webhook(
+    _: Throttle<WebhookTests>,
+    auth: RequireLogin,
+    Path(id): Path<Ulid>,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<WebhookDeliveryItem>, ApiError> {
+    let hook = managed(&state, &auth, id).await?;
+
+    let payload = serde_json::to_string(&WebhookPayload {
+        event: WebhookEvent::Ping,
+        actor: None,
+        post: None,
+        comment: None,
+        occurred_at: Utc::now().into(),
+    })
+    .expect("WebhookPayload serializes to json");
+
+    let delivery = log_delivery(&state, &hook, WebhookEvent::Ping, &payload)
+        .await
+        .map_err(Error::from_query)?;
+
+    let delivery = attempt_and_log(&state, &hook, delivery.id, WebhookEvent::Ping, &payload)
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(delivery_item(delivery)))
+}
+
+pub fn routes() -> Router<BlogDrownState> {
+    Router::new()
+        .route("/", get(list_webhooks).post(create_webhook))
+        .route("/:webhookId", delete(delete_webhook))
+        .route("/:webhookId/deliveries", get(list_deliveries))
+        .route("/:webhookId/test", post(test_webhook))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::http::HeaderMap;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const SECRET: &str = "secret";
+    const PAYLOAD: &str = r#"{"event":"Ping"}"#;
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+    /// Answers every delivery with `status` on a local port, keeping what it received
+    async fn listener(status: StatusCode) -> (Url, Received) {
+        let received = Received::default();
+
+        let app = Router::new().route(
+            "/",
+            post({
+                let received = received.clone();
+                move |headers: HeaderMap, body: String| async move {
+                    received.lock().unwrap().push((headers, body));
+                    status
+                }
+            }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        (url, received)
+    }
+
+    async fn send_ping(url: Url) -> Attempt {
+        send(
+            &reqwest::Client::new(),
+            url,
+            SECRET,
+            7,
+            WebhookEvent::Ping,
+            PAYLOAD,
+        )
+        .await
+    }
+
+    #[test]
+    fn signature_format() {
+        assert_eq!(
+            sign(SECRET, 1_700_000_000, PAYLOAD),
+            "sha384=27b6afaf8bba590d530676a646d00db006326a81fe3e37f2666d1987238e1db7a85ee9b03c46c2c9dce75d91d187cecb"
+        );
+    }
+
+    #[test]
+    fn retries_with_growing_backoff() {
+        let waits: Vec<_> = waits().collect();
+
+        assert_eq!(waits.len(), BACKOFF.len() + 1);
+        assert_eq!(waits[0], Duration::ZERO);
+        assert!(waits.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[tokio::test]
+    async fn signs_deliveries() {
+        let (url, received) = listener(StatusCode::NO_CONTENT).await;
+
+        let attempt = send_ping(url).await;
+        assert_eq!(attempt.status, Some(204));
+        assert_eq!(attempt.error, None);
+
+        let received = received.lock().unwrap();
+        let (headers, body) = &received[0];
+        let header = |name: &str| headers[name].to_str().unwrap();
+
+        assert_eq!(body, PAYLOAD);
+        assert_eq!(header("content-type"), "application/json");
+        assert_eq!(header("x-blogdrown-event"), "Ping");
+        assert_eq!(header("x-blogdrown-delivery"), "7");
+
+        let timestamp = header("x-blogdrown-timestamp").parse().unwrap();
+        assert_eq!(
+            header("x-blogdrown-signature"),
+            sign(SECRET, timestamp, PAYLOAD)
+        );
+    }
+
+    #[tokio::test]
+    async fn records_error_responses() {
+        let (url, _) = listener(StatusCode::INTERNAL_SERVER_ERROR).await;
+
+        let attempt = send_ping(url).await;
+        assert_eq!(attempt.status, Some(500));
+        assert_eq!(
+            attempt.error.as_deref(),
+            Some("Responded with 500 Internal Server Error")
+        );
+    }
+
+    #[tokio::test]
+    async fn records_connection_errors() {
+        // bound and dropped again, so nothing listens on the port
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let attempt = send_ping(Url::parse(&format!("http://{addr}/")).unwrap()).await;
+        assert_eq!(attempt.status, None);
+        assert!(attempt.error.is_some_and(|e| e.contains("refused")));
+    }
+}
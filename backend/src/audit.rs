@@ -0,0 +1,42 @@
+//! Append-only record of security and moderation relevant events.
+//!
+//! Actor and target are stored as plain ids without foreign keys, so events outlive
+//! the accounts and content they refer to.
+
+use std::net::IpAddr;
+
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{prisma::AuditKind, BlogDrownState};
+
+/// Records an audit event, failures are logged but never fail the request being audited
+pub async fn record(
+    state: &BlogDrownState,
+    kind: AuditKind,
+    actor: Option<Ulid>,
+    target: Option<Ulid>,
+    ip: Option<IpAddr>,
+    detail: Option<String>,
+) {
+    use crate::prisma::audit_event;
+
+    let res = state
+        .prisma
+        .audit_event()
+        .create(
+            kind,
+            vec![
+                audit_event::actor_id::set(actor.map(|id| Uuid::from(id).to_string())),
+                audit_event::target_id::set(target.map(|id| Uuid::from(id).to_string())),
+                audit_event::ip::set(ip.map(|ip| ip.to_string())),
+                audit_event::detail::set(detail),
+            ],
+        )
+        .exec()
+        .await;
+
+    if let Err(e) = res {
+        tracing::error!("failed to record audit event {kind:?}: {e}");
+    }
+}
@@ -1,8 +1,10 @@
+use std::net::IpAddr;
+
 use axum::{
     async_trait,
     extract::{FromRequestParts, State},
-    http::{request::Parts, StatusCode},
-    response::{IntoResponse, IntoResponseParts},
+    http::{header::AUTHORIZATION, request::Parts, Method, StatusCode},
+    response::{IntoResponse, IntoResponseParts, Response},
     routing::{get, post},
     Json, Router,
 };
@@ -13,21 +15,35 @@ use axum_extra::extract::{
 use chrono::{DateTime, TimeDelta, Utc};
 use jwt::{SignWithKey, VerifyWithKey};
 use prisma_client_rust::or;
-use scrypt::{
-    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Scrypt,
-};
-use secrecy::{ExposeSecret, SecretString};
 use ulid::Ulid;
 use uuid::Uuid;
 
 use crate::{
-    api::{ApiError, ApiJson, AuthUser, Created, Error, Login, MinUser, Signup},
+    api::{
+        ApiError, ApiJson, AuthUser, Created, CsrfToken, Error, Login, MinUser, Profile,
+        RecoveryCodes, RegistrationInfo, SessionToken, Signup, TotpConfirm, TotpDisable,
+        TotpEnrollment,
+    },
+    audit,
     bounded::BoundString,
-    prisma, BlogDrownState,
+    ip::ClientIp,
+    prisma::{self, AuditKind},
+    ratelimit::{AuthAttempts, Throttle},
+    BlogDrownState,
 };
 
+pub mod csrf;
+pub mod password;
+mod registration;
+mod roles;
+mod totp;
+
+pub use registration::Registration;
+pub use roles::{at_least, Admin, Moderator, RequireRole};
+
 const SESSION_COOKIE: &str = "session";
+const SESSION_DAYS: i64 = 30;
+const TOTP_ISSUER: &str = "BlogDrown";
 
 fn session_cookie<'a>(value: impl Into<String>, production: bool) -> Cookie<'a> {
     let mut session_cookie = Cookie::new(SESSION_COOKIE, value.into());
@@ -43,6 +59,35 @@ fn session_cookie<'a>(value: impl Into<String>, production: bool) -> Cookie<'a>
     session_cookie
 }
 
+fn csrf_cookie<'a>(value: impl Into<String>, production: bool) -> Cookie<'a> {
+    let mut csrf_cookie = Cookie::new(csrf::CSRF_COOKIE, value.into());
+
+    // must stay readable from scripts so it can be echoed back in the header
+    csrf_cookie.set_same_site(SameSite::Lax);
+    csrf_cookie.set_path("/");
+
+    if production {
+        csrf_cookie.set_secure(true);
+    }
+
+    csrf_cookie
+}
+
+fn sign_session(id: Ulid, state: &BlogDrownState) -> String {
+    RequireLogin {
+        id,
+        creat: Utc::now(),
+    }
+    .sign(state)
+}
+
+fn add_session(jar: CookieJar, token: String, state: &BlogDrownState) -> CookieJar {
+    let csrf = csrf::token(&state.jwt_secret, &token);
+
+    jar.add(session_cookie(token, state.production))
+        .add(csrf_cookie(csrf, state.production))
+}
+
 #[derive(serde_derive::Serialize, serde_derive::Deserialize)]
 pub struct RequireLogin {
     pub id: ulid::Ulid,
@@ -53,45 +98,56 @@ impl RequireLogin {
     pub fn uuid(&self) -> String {
         Uuid::from(self.id).to_string()
     }
-}
 
-fn scrypt_hash(password: SecretString, production: bool) -> String {
-    use scrypt::Params;
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.creat + TimeDelta::days(SESSION_DAYS)
+    }
 
-    let scrypt_params = (if production {
-        Params::new(
-            Params::RECOMMENDED_LOG_N - 3,
-            Params::RECOMMENDED_R + 2,
-            Params::RECOMMENDED_P + 1,
-            Params::RECOMMENDED_LEN,
-        )
-    } else {
-        Params::new(5, 5, Params::RECOMMENDED_P, Params::RECOMMENDED_LEN)
-    })
-    .expect("valid config");
+    fn sign(&self, state: &BlogDrownState) -> String {
+        self.sign_with_key(&state.jwt_secret)
+            .expect("RequireLogin is valid serde_json")
+    }
 
-    let salt = SaltString::generate(&mut rand::thread_rng());
+    /// Why the session may no longer be used, if it may not
+    pub async fn revoked(
+        &self,
+        state: &BlogDrownState,
+    ) -> Result<Option<Revoked>, prisma_client_rust::QueryError> {
+        if self.expires_at() < Utc::now() {
+            return Ok(Some(Revoked::Ended));
+        }
 
-    Scrypt
-        .hash_password_customized(
-            password.expose_secret().as_bytes(),
-            None,
-            None,
-            scrypt_params,
-            &salt,
-        )
-        .expect("Scrypt must not fail to hash password")
-        .to_string()
+        let Some(user) = state
+            .prisma
+            .user()
+            .find_unique(prisma::user::id::equals(self.uuid()))
+            .select(prisma::user::select!({ suspended_at sessions_valid_after }))
+            .exec()
+            .await?
+        else {
+            return Ok(Some(Revoked::Ended));
+        };
+
+        // sessions issued before a forced logout, password reset or account deletion are revoked
+        if user
+            .sessions_valid_after
+            .is_some_and(|after| self.creat < after)
+        {
+            return Ok(Some(Revoked::Ended));
+        }
+
+        if user.suspended_at.is_some() {
+            return Ok(Some(Revoked::Suspended));
+        }
+
+        Ok(None)
+    }
 }
 
-fn scrypt_verify(password: SecretString, hash: String) -> Result<(), ()> {
-    Scrypt
-        .verify_password(
-            password.expose_secret().as_bytes(),
-            &PasswordHash::new(&hash).map_err(|_| ())?,
-        )
-        .map_err(|_| ())
-        .map(|_| ())
+pub enum Revoked {
+    /// Expired, logged out elsewhere or the account is gone
+    Ended,
+    Suspended,
 }
 
 #[async_trait]
@@ -109,42 +165,75 @@ impl FromRequestParts<BlogDrownState> for RequireLogin {
             )
         };
 
-        let Ok(jar) = CookieJar::from_request_parts(parts, state).await;
-
-        let Some(cookie) = jar.get(SESSION_COOKIE) else {
-            return Err(reject());
+        let token = if let Some(header) = parts.headers.get(AUTHORIZATION) {
+            // bearer tokens are never sent implicitly by browsers so need no CSRF check
+            header
+                .to_str()
+                .ok()
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .ok_or_else(reject)?
+                .to_owned()
+        } else {
+            let Ok(jar) = CookieJar::from_request_parts(parts, state).await;
+
+            let Some(cookie) = jar.get(SESSION_COOKIE) else {
+                return Err(reject());
+            };
+
+            let safe = matches!(parts.method, Method::GET | Method::HEAD | Method::OPTIONS);
+
+            let csrf_ok = parts
+                .headers
+                .get(csrf::CSRF_HEADER)
+                .and_then(|h| h.to_str().ok())
+                .is_some_and(|t| csrf::verify(&state.jwt_secret, cookie.value(), t));
+
+            if !(safe || csrf_ok) {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    Json(Error::new("Missing or invalid CSRF token")),
+                ));
+            }
+
+            cookie.value().to_owned()
         };
 
-        let login: RequireLogin = cookie
-            .value()
+        let login: RequireLogin = token
             .verify_with_key(&state.jwt_secret)
             .map_err(|_| reject())?;
 
-        if (login.creat + TimeDelta::days(30)) < Utc::now() {
-            return Err(reject());
+        match login.revoked(state).await {
+            Ok(None) => Ok(login),
+            Ok(Some(Revoked::Suspended)) => Err(suspended(None)),
+            Ok(Some(Revoked::Ended)) | Err(_) => Err(reject()),
         }
+    }
+}
 
-        let exists = state
-            .prisma
-            .user()
-            .find_unique(prisma::user::id::equals(login.uuid()))
-            .select(prisma::user::select!({ id }))
-            .exec()
-            .await;
-
-        if !exists.is_ok_and(|v| v.is_some()) {
-            return Err(reject());
-        }
+fn suspended(reason: Option<String>) -> ApiError {
+    let mut err = Error::new("Account is suspended");
 
-        Ok(login)
+    if let Some(reason) = reason {
+        err.add("reason", reason);
     }
+
+    (StatusCode::FORBIDDEN, Json(err))
 }
 
 macro_rules! authuser {
     ($user:ident) => {
         Json(AuthUser {
             email: BoundString::new_unchecked($user.email),
+            pending_email: $user.pending_email.map(BoundString::new_unchecked),
+            profile: Profile {
+                display_name: $user.display_name.map(BoundString::new_unchecked),
+                bio: $user.bio.map(BoundString::new_unchecked),
+                avatar_url: $user.avatar_url.map(BoundString::new_unchecked),
+                website: $user.website.map(BoundString::new_unchecked),
+            },
             created_at: $user.created_at,
+            role: $user.role,
+            totp_enabled: $user.totp_enabled,
             min: MinUser {
                 id: $user.id.parse::<Uuid>().expect("schema is uuid").into(),
                 username: BoundString::new_unchecked($user.username),
@@ -155,31 +244,48 @@ macro_rules! authuser {
 
 async fn auth_info(
     user: RequireLogin,
+    jar: CookieJar,
     State(state): State<BlogDrownState>,
-) -> Result<Json<AuthUser>, ApiError> {
+) -> Result<(CookieJar, Json<AuthUser>), ApiError> {
     use prisma::user;
 
     let user = state
         .prisma
         .user()
         .find_unique(user::id::equals(user.uuid()))
-        .select(user::select!({id email username created_at}))
+        .select(user::select!({
+            id email username created_at totp_enabled role
+            pending_email display_name bio avatar_url website
+        }))
         .exec()
         .await
         .map_err(Error::from_query)?
         .ok_or_else(Error::not_found)?;
 
-    Ok(authuser!(user))
+    // sessions created before CSRF tokens existed pick up their cookie here
+    let jar = match jar.get(SESSION_COOKIE) {
+        Some(session) if jar.get(csrf::CSRF_COOKIE).is_none() => {
+            let token = csrf::token(&state.jwt_secret, session.value());
+            jar.add(csrf_cookie(token, state.production))
+        }
+        _ => jar,
+    };
+
+    Ok((jar, authuser!(user)))
 }
 
 async fn signup(
+    _: Throttle<AuthAttempts>,
+    ClientIp(ip): ClientIp,
     jar: CookieJar,
     State(state): State<BlogDrownState>,
     ApiJson(signup): ApiJson<Signup>,
 ) -> Result<(impl IntoResponseParts, Created<Json<AuthUser>>), ApiError> {
-    let query = state.prisma;
+    registration::check(state.registration, signup.invite.as_deref())?;
 
-    use prisma::user;
+    let query = &state.prisma;
+
+    use prisma::{invite, user};
 
     let existing = query
         .user()
@@ -229,89 +335,586 @@ async fn signup(
         return Err((StatusCode::BAD_REQUEST, Json(err)));
     }
 
-    let user = query
-        .user()
-        .create(
-            Uuid::now_v7().to_string(),
-            signup.username.into_inner(),
-            signup.email.into_inner(),
-            scrypt_hash(signup.password, state.production),
-            vec![],
-        )
-        .select(user::select!({id email username created_at}))
-        .exec()
+    // invite codes are only consulted when registration requires them
+    let invite = match state.registration {
+        Registration::Invite => signup.invite.map(BoundString::into_inner),
+        _ => None,
+    };
+
+    let tx = query
+        ._transaction()
+        .begin()
         .await
         .map_err(Error::from_query)?;
 
+    if let Some(code) = &invite {
+        if let Err(e) = registration::claim(&tx.1, code).await {
+            tx.0.rollback(tx.1).await.map_err(Error::from_query)?;
+            return Err(e);
+        }
+    }
+
+    let user =
+        tx.1.user()
+            .create(
+                Uuid::now_v7().to_string(),
+                signup.username.into_inner(),
+                signup.email.into_inner(),
+                password::hash(&signup.password, state.production),
+                invite
+                    .map(|code| vec![user::invited_by::connect(invite::code::equals(code))])
+                    .unwrap_or_default(),
+            )
+            .select(user::select!({
+                id email username created_at totp_enabled role
+                pending_email display_name bio avatar_url website
+            }))
+            .exec()
+            .await
+            .map_err(Error::from_query)?;
+
+    tx.0.commit(tx.1).await.map_err(Error::from_query)?;
+
     let ulid_id: Ulid = user.id.parse::<Uuid>().expect("schema is uuid").into();
 
-    let token = RequireLogin {
-        id: ulid_id,
-        creat: Utc::now(),
-    }
-    .sign_with_key(&state.jwt_secret)
-    .expect("RequireLogin is valid serde_json");
+    audit::record(
+        &state,
+        AuditKind::Signup,
+        Some(ulid_id),
+        None,
+        Some(ip),
+        None,
+    )
+    .await;
 
-    Ok((
-        jar.add(session_cookie(token, state.production)),
-        Created(authuser!(user)),
-    ))
+    let token = sign_session(ulid_id, &state);
+
+    Ok((add_session(jar, token, &state), Created(authuser!(user))))
 }
 
 async fn login(
+    _: Throttle<AuthAttempts>,
+    ClientIp(ip): ClientIp,
     jar: CookieJar,
     State(state): State<BlogDrownState>,
     ApiJson(login): ApiJson<Login>,
-) -> Result<(impl IntoResponseParts, Json<AuthUser>), ApiError> {
+) -> Result<(CookieJar, Json<AuthUser>), Response> {
+    let account = login.email.to_lowercase();
+
+    // the 429 itself so it carries Retry-After like the address throttle
+    state
+        .limits
+        .login_account
+        .check(&account)
+        .map_err(IntoResponse::into_response)?;
+
+    authenticate(&state, ip, jar, login, &account)
+        .await
+        .map_err(IntoResponse::into_response)
+}
+
+/// Checks the credentials of a login that is not locked out, counting failures against `account`
+async fn authenticate(
+    state: &BlogDrownState,
+    ip: IpAddr,
+    jar: CookieJar,
+    login: Login,
+    account: &str,
+) -> Result<(CookieJar, Json<AuthUser>), ApiError> {
     use prisma::user;
 
-    let bad_creds = Err((
-        StatusCode::UNAUTHORIZED,
-        Json(Error::new("Bad Credentials")),
-    ));
+    let limiter = &state.limits.login_account;
+
+    let bad_creds = || {
+        limiter.failure(account);
+
+        Err((
+            StatusCode::UNAUTHORIZED,
+            Json(Error::new("Bad Credentials")),
+        ))
+    };
 
     let user = state
         .prisma
         .user()
         .find_unique(user::email::equals(login.email.clone().into_inner()))
         .select(user::select!({
-            id password email created_at username
+            id password email created_at username role totp_enabled totp_secret totp_last_step
+            pending_email display_name bio avatar_url website suspended_at suspension_reason
         }))
         .exec()
         .await
         .map_err(Error::from_query)?;
 
-    let Some(user) = user else {
-        return bad_creds;
+    let failed = |actor: Option<Ulid>, reason: &str| {
+        audit::record(
+            state,
+            AuditKind::LoginFailure,
+            actor,
+            None,
+            Some(ip),
+            Some(format!("{reason} for {account}")),
+        )
     };
 
-    let Ok(()) = scrypt_verify(login.password, user.password) else {
-        return bad_creds;
+    let Some(user) = user else {
+        failed(None, "unknown account").await;
+        return bad_creds();
     };
 
     let ulid_id: Ulid = user.id.parse::<Uuid>().expect("schema is uuid").into();
 
-    let token = RequireLogin {
-        id: ulid_id,
-        creat: Utc::now(),
+    let Ok(verified) = password::verify(&login.password, &user.password, state.production) else {
+        failed(Some(ulid_id), "wrong password").await;
+        return bad_creds();
+    };
+
+    if user.totp_enabled {
+        let second_factor = verify_second_factor(
+            state,
+            &user.id,
+            user.totp_secret,
+            user.totp_last_step,
+            login.totp.as_deref(),
+            login.recovery_code.as_deref(),
+        )
+        .await;
+
+        if let Err(e) = second_factor {
+            // the first step of a two-factor login sends no code and only checks the password
+            if login.totp.is_some() || login.recovery_code.is_some() {
+                limiter.failure(account);
+                failed(Some(ulid_id), "second factor rejected").await;
+            }
+
+            return Err(e);
+        }
+    }
+
+    limiter.success(account);
+
+    if user.suspended_at.is_some() {
+        failed(Some(ulid_id), "suspended account").await;
+        return Err(suspended(user.suspension_reason));
+    }
+
+    if verified == password::Verified::Outdated {
+        let rehashed = state
+            .prisma
+            .user()
+            .update(
+                user::id::equals(user.id.clone()),
+                vec![user::password::set(password::hash(
+                    &login.password,
+                    state.production,
+                ))],
+            )
+            .exec()
+            .await;
+
+        if let Err(e) = rehashed {
+            tracing::warn!("failed to upgrade password hash for User({}): {e}", user.id);
+        }
+    }
+
+    audit::record(
+        state,
+        AuditKind::LoginSuccess,
+        Some(ulid_id),
+        None,
+        Some(ip),
+        None,
+    )
+    .await;
+
+    let token = sign_session(ulid_id, state);
+
+    Ok((add_session(jar, token, state), authuser!(user)))
+}
+
+/// Second login step for accounts with two-factor authentication enabled,
+/// accepts either a current TOTP code or an unused recovery code
+async fn verify_second_factor(
+    state: &BlogDrownState,
+    user_id: &str,
+    secret: Option<String>,
+    last_step: Option<i64>,
+    totp_code: Option<&str>,
+    recovery: Option<&str>,
+) -> Result<(), ApiError> {
+    use prisma::{recovery_code, user};
+
+    let bad_code = |field: &str| {
+        let mut err = Error::new("Bad Credentials");
+        err.add(field, "Code is invalid or has already been used");
+        Err((StatusCode::UNAUTHORIZED, Json(err)))
+    };
+
+    if let Some(code) = totp_code {
+        let Some(step) = secret
+            .as_deref()
+            .and_then(|secret| totp::verify(secret, code, Utc::now(), last_step))
+        else {
+            return bad_code("totp");
+        };
+
+        // guards against a concurrent login replaying the same code
+        let updated = state
+            .prisma
+            .user()
+            .update_many(
+                vec![
+                    user::id::equals(user_id.to_owned()),
+                    or![
+                        user::totp_last_step::equals(None),
+                        user::totp_last_step::lt(step)
+                    ],
+                ],
+                vec![user::totp_last_step::set(Some(step))],
+            )
+            .exec()
+            .await
+            .map_err(Error::from_query)?;
+
+        if updated == 0 {
+            return bad_code("totp");
+        }
+
+        return Ok(());
+    }
+
+    if let Some(code) = recovery {
+        let used = state
+            .prisma
+            .recovery_code()
+            .update_many(
+                vec![
+                    recovery_code::user_id::equals(user_id.to_owned()),
+                    recovery_code::hash::equals(totp::hash_recovery_code(code)),
+                    recovery_code::used_at::equals(None),
+                ],
+                vec![recovery_code::used_at::set(Some(Utc::now().into()))],
+            )
+            .exec()
+            .await
+            .map_err(Error::from_query)?;
+
+        if used == 0 {
+            return bad_code("recovery_code");
+        }
+
+        return Ok(());
+    }
+
+    let mut err = Error::new("Two-factor authentication required");
+    err.add(
+        "totp",
+        "A code from your authenticator app or a recovery code is required",
+    );
+
+    Err((StatusCode::UNAUTHORIZED, Json(err)))
+}
+
+async fn totp_enroll(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+) -> Result<Json<TotpEnrollment>, ApiError> {
+    use prisma::user;
+
+    let user = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(auth.uuid()))
+        .select(user::select!({ email totp_enabled }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    if user.totp_enabled {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(Error::new("Two-factor authentication is already enabled")),
+        ));
+    }
+
+    let secret = totp::generate_secret();
+
+    state
+        .prisma
+        .user()
+        .update(
+            user::id::equals(auth.uuid()),
+            vec![
+                user::totp_secret::set(Some(secret.clone())),
+                user::totp_last_step::set(None),
+            ],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    Ok(Json(TotpEnrollment {
+        otpauth_uri: totp::otpauth_uri(TOTP_ISSUER, &user.email, &secret),
+        secret,
+    }))
+}
+
+async fn totp_confirm(
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+    ApiJson(confirm): ApiJson<TotpConfirm>,
+) -> Result<Created<Json<RecoveryCodes>>, ApiError> {
+    use prisma::{recovery_code, user};
+
+    let user = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(auth.uuid()))
+        .select(user::select!({ totp_secret totp_enabled }))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+        .ok_or_else(Error::not_found)?;
+
+    if user.totp_enabled {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(Error::new("Two-factor authentication is already enabled")),
+        ));
     }
-    .sign_with_key(&state.jwt_secret)
-    .expect("RequireLogin is valid serde_json");
+
+    let Some(secret) = user.totp_secret else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(Error::new("Two-factor enrollment has not been started")),
+        ));
+    };
+
+    let Some(step) = totp::verify(&secret, &confirm.code, Utc::now(), None) else {
+        let mut err = Error::new("Invalid two-factor code");
+        err.add("code", "Code does not match the enrolled secret");
+        return Err((StatusCode::BAD_REQUEST, Json(err)));
+    };
+
+    let codes = totp::generate_recovery_codes();
+
+    let tx = state
+        .prisma
+        ._transaction()
+        .begin()
+        .await
+        .map_err(Error::from_query)?;
+
+    tx.1.recovery_code()
+        .delete_many(vec![recovery_code::user_id::equals(auth.uuid())])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    tx.1.recovery_code()
+        .create_many(
+            codes
+                .iter()
+                .map(|c| {
+                    recovery_code::create_unchecked(
+                        auth.uuid(),
+                        totp::hash_recovery_code(c),
+                        vec![],
+                    )
+                })
+                .collect(),
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    tx.1.user()
+        .update(
+            user::id::equals(auth.uuid()),
+            vec![
+                user::totp_enabled::set(true),
+                user::totp_last_step::set(Some(step)),
+            ],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    tx.0.commit(tx.1).await.map_err(Error::from_query)?;
+
+    Ok(Created::json(RecoveryCodes {
+        recovery_codes: codes,
+    }))
+}
+
+async fn totp_disable(
+    _: Throttle<AuthAttempts>,
+    auth: RequireLogin,
+    State(state): State<BlogDrownState>,
+    ApiJson(disable): ApiJson<TotpDisable>,
+) -> Result<(), Response> {
+    use prisma::user;
+
+    let user = state
+        .prisma
+        .user()
+        .find_unique(user::id::equals(auth.uuid()))
+        .select(user::select!({ email password totp_enabled totp_secret totp_last_step }))
+        .exec()
+        .await
+        .map_err(Error::from_query)
+        .and_then(|user| user.ok_or_else(Error::not_found))
+        .map_err(IntoResponse::into_response)?;
+
+    // shares the login limit, otherwise a stolen session could guess the password here
+    let account = user.email.to_lowercase();
+    let limiter = &state.limits.login_account;
+
+    limiter
+        .check(&account)
+        .map_err(IntoResponse::into_response)?;
+
+    let Ok(_) = password::verify(&disable.password, &user.password, state.production) else {
+        limiter.failure(&account);
+
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(Error::new("Bad Credentials")),
+        )
+            .into_response());
+    };
+
+    // an enrollment that was never confirmed can be cancelled with the password alone
+    if user.totp_enabled {
+        let second_factor = verify_second_factor(
+            &state,
+            &auth.uuid(),
+            user.totp_secret,
+            user.totp_last_step,
+            disable.totp.as_deref(),
+            disable.recovery_code.as_deref(),
+        )
+        .await;
+
+        if let Err(e) = second_factor {
+            limiter.failure(&account);
+            return Err(e.into_response());
+        }
+    }
+
+    limiter.success(&account);
+
+    remove_totp(&state, auth.uuid())
+        .await
+        .map_err(IntoResponse::into_response)
+}
+
+/// Clears the secret and recovery codes of a user
+async fn remove_totp(state: &BlogDrownState, user_id: String) -> Result<(), ApiError> {
+    use prisma::{recovery_code, user};
+
+    let tx = state
+        .prisma
+        ._transaction()
+        .begin()
+        .await
+        .map_err(Error::from_query)?;
+
+    tx.1.recovery_code()
+        .delete_many(vec![recovery_code::user_id::equals(user_id.clone())])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    tx.1.user()
+        .update(
+            user::id::equals(user_id),
+            vec![
+                user::totp_enabled::set(false),
+                user::totp_secret::set(None),
+                user::totp_last_step::set(None),
+            ],
+        )
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    tx.0.commit(tx.1).await.map_err(Error::from_query)?;
+
+    Ok(())
+}
+
+pub fn clear_session(jar: CookieJar, production: bool) -> CookieJar {
+    jar.remove(session_cookie("", production))
+        .remove(csrf_cookie("", production))
+}
+
+async fn logout(jar: CookieJar, State(state): State<BlogDrownState>) -> impl IntoResponse {
+    clear_session(jar, state.production)
+}
+
+/// Returns the CSRF token for the current session cookie, refreshing the readable cookie
+async fn csrf_token(
+    _: RequireLogin,
+    jar: CookieJar,
+    State(state): State<BlogDrownState>,
+) -> Result<(CookieJar, Json<CsrfToken>), ApiError> {
+    let Some(session) = jar.get(SESSION_COOKIE) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(Error::new("CSRF tokens only apply to cookie sessions")),
+        ));
+    };
+
+    let token = csrf::token(&state.jwt_secret, session.value());
 
     Ok((
-        jar.add(session_cookie(token, state.production)),
-        authuser!(user),
+        jar.add(csrf_cookie(token.clone(), state.production)),
+        Json(CsrfToken { token }),
     ))
 }
 
-async fn logout(jar: CookieJar, State(state): State<BlogDrownState>) -> impl IntoResponse {
-    jar.remove(session_cookie("", state.production))
+async fn registration_info(State(state): State<BlogDrownState>) -> Json<RegistrationInfo> {
+    Json(RegistrationInfo {
+        mode: state.registration,
+    })
+}
+
+/// Issues a bearer token for API clients that do not use cookies.
+///
+/// The token keeps the issue time of the session it is made from, so it expires along with it
+/// and cannot be used to mint tokens that outlive the original login.
+async fn create_token(
+    auth: RequireLogin,
+    ClientIp(ip): ClientIp,
+    State(state): State<BlogDrownState>,
+) -> Created<Json<SessionToken>> {
+    audit::record(
+        &state,
+        AuditKind::TokenCreate,
+        Some(auth.id),
+        None,
+        Some(ip),
+        None,
+    )
+    .await;
+
+    Created::json(SessionToken {
+        token: auth.sign(&state),
+    })
 }
 
 pub fn routes() -> Router<BlogDrownState> {
     Router::new()
         .route("/", get(auth_info))
         .route("/signup", post(signup))
+        .route("/registration", get(registration_info))
         .route("/login", post(login))
         .route("/logout", post(logout))
+        .route("/csrf", get(csrf_token))
+        .route("/token", post(create_token))
+        .route("/totp/enroll", post(totp_enroll))
+        .route("/totp/confirm", post(totp_confirm))
+        .route("/totp/disable", post(totp_disable))
 }
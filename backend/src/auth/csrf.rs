@@ -0,0 +1,31 @@
+//! Signed CSRF tokens for cookie authenticated requests.
+//!
+//! The token is a MAC of the session token, so it needs no storage and is
+//! invalidated together with the session it belongs to.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha384;
+
+pub const CSRF_COOKIE: &str = "csrf";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+const CONTEXT: &[u8] = b"blogdrown-csrf:";
+
+fn mac(secret: &Hmac<Sha384>, session: &str) -> Hmac<Sha384> {
+    let mut mac = secret.clone();
+    mac.update(CONTEXT);
+    mac.update(session.as_bytes());
+    mac
+}
+
+pub fn token(secret: &Hmac<Sha384>, session: &str) -> String {
+    hex::encode(mac(secret, session).finalize().into_bytes())
+}
+
+pub fn verify(secret: &Hmac<Sha384>, session: &str, token: &str) -> bool {
+    let Ok(token) = hex::decode(token) else {
+        return false;
+    };
+
+    mac(secret, session).verify_slice(&token).is_ok()
+}
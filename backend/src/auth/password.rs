@@ -0,0 +1,72 @@
+//! Password hashing, Argon2id is preferred while existing scrypt hashes are still accepted.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use scrypt::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Scrypt,
+};
+use secrecy::{ExposeSecret, SecretString};
+
+/// Whether a successfully verified hash should be replaced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verified {
+    Current,
+    Outdated,
+}
+
+fn argon2_params(production: bool) -> Params {
+    (if production {
+        // OWASP recommended minimum: 19 MiB, 2 iterations, 1 lane
+        Params::new(19 * 1024, 2, 1, None)
+    } else {
+        Params::new(1024, 1, 1, None)
+    })
+    .expect("valid config")
+}
+
+pub fn hash(password: &SecretString, production: bool) -> String {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+
+    Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        argon2_params(production),
+    )
+    .hash_password(password.expose_secret().as_bytes(), &salt)
+    .expect("Argon2 must not fail to hash password")
+    .to_string()
+}
+
+/// Verifies `password` against a PHC string produced by either Argon2 or scrypt
+pub fn verify(password: &SecretString, hash: &str, production: bool) -> Result<Verified, ()> {
+    let hash = PasswordHash::new(hash).map_err(|_| ())?;
+    let password = password.expose_secret().as_bytes();
+
+    match Algorithm::try_from(hash.algorithm) {
+        Ok(algorithm) => {
+            Argon2::default()
+                .verify_password(password, &hash)
+                .map_err(|_| ())?;
+
+            let current = argon2_params(production);
+            let outdated = algorithm != Algorithm::Argon2id
+                || hash.version != Some(Version::V0x13.into())
+                || Params::try_from(&hash).map_or(true, |p| {
+                    p.m_cost() < current.m_cost()
+                        || p.t_cost() < current.t_cost()
+                        || p.p_cost() < current.p_cost()
+                });
+
+            Ok(if outdated {
+                Verified::Outdated
+            } else {
+                Verified::Current
+            })
+        }
+        Err(_) => {
+            Scrypt.verify_password(password, &hash).map_err(|_| ())?;
+
+            Ok(Verified::Outdated)
+        }
+    }
+}
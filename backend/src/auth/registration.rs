@@ -0,0 +1,101 @@
+use std::str::FromStr;
+
+use axum::{http::StatusCode, Json};
+use chrono::Utc;
+use prisma_client_rust::or;
+use serde_derive::Serialize;
+
+use crate::{
+    api::{ApiError, Error},
+    prisma::{invite, PrismaClient},
+};
+
+/// Who may create an account, configured by `BLOGDROWN_REGISTRATION`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Registration {
+    #[default]
+    Open,
+    /// Signups must present a valid invite code
+    Invite,
+    Closed,
+}
+
+impl FromStr for Registration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "open" => Ok(Self::Open),
+            "invite" => Ok(Self::Invite),
+            "closed" => Ok(Self::Closed),
+            _ => Err(format!(
+                "Invalid registration mode {s}, expected open, invite or closed"
+            )),
+        }
+    }
+}
+
+fn rejected(field: &str, reason: &str) -> ApiError {
+    let mut err = Error::new("Registration not permitted");
+    err.add(field, reason);
+
+    (StatusCode::FORBIDDEN, Json(err))
+}
+
+/// Rejects signups the registration mode does not permit before any other validation
+pub fn check(mode: Registration, invite: Option<&str>) -> Result<(), ApiError> {
+    match (mode, invite) {
+        (Registration::Closed, _) => Err(rejected(
+            "registration",
+            "This instance is not accepting new accounts",
+        )),
+        (Registration::Invite, None) => {
+            Err(rejected("invite", "An invite code is required to sign up"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Uses up one use of the invite code, meant to run in the signup transaction
+pub async fn claim(client: &PrismaClient, code: &str) -> Result<(), ApiError> {
+    let invalid = || rejected("invite", "Invite code is invalid, expired or used up");
+
+    let Some(found) = client
+        .invite()
+        .find_unique(invite::code::equals(code.to_owned()))
+        .exec()
+        .await
+        .map_err(Error::from_query)?
+    else {
+        return Err(invalid());
+    };
+
+    let mut usable = vec![
+        invite::code::equals(found.code),
+        invite::revoked_at::equals(None),
+        or![
+            invite::expires_at::equals(None),
+            invite::expires_at::gt(Utc::now().into())
+        ],
+    ];
+
+    // the limit never changes, so checking it in the update claims a use atomically
+    // and concurrent signups only fail once the invite is actually used up
+    if let Some(max) = found.max_uses {
+        usable.push(invite::uses::lt(max));
+    }
+
+    let claimed = client
+        .invite()
+        .update_many(usable, vec![invite::uses::increment(1)])
+        .exec()
+        .await
+        .map_err(Error::from_query)?;
+
+    if claimed == 0 {
+        return Err(invalid());
+    }
+
+    Ok(())
+}
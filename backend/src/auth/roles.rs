@@ -0,0 +1,97 @@
+use std::marker::PhantomData;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+    Json,
+};
+
+use crate::{
+    api::{ApiError, Error},
+    prisma::{self, Role},
+    BlogDrownState,
+};
+
+use super::RequireLogin;
+
+fn rank(role: Role) -> u8 {
+    match role {
+        Role::User => 0,
+        Role::Moderator => 1,
+        Role::Admin => 2,
+    }
+}
+
+/// Whether `role` grants at least the permissions of `required`
+pub fn at_least(role: Role, required: Role) -> bool {
+    rank(role) >= rank(required)
+}
+
+impl RequireLogin {
+    pub async fn role(&self, state: &BlogDrownState) -> Result<Role, ApiError> {
+        use prisma::user;
+
+        let user = state
+            .prisma
+            .user()
+            .find_unique(user::id::equals(self.uuid()))
+            .select(user::select!({ role }))
+            .exec()
+            .await
+            .map_err(Error::from_query)?
+            .ok_or_else(Error::not_found)?;
+
+        Ok(user.role)
+    }
+}
+
+/// Minimum role accepted by [`RequireRole`]
+pub trait MinRole: Send + Sync + 'static {
+    const ROLE: Role;
+}
+
+pub struct Moderator;
+
+impl MinRole for Moderator {
+    const ROLE: Role = Role::Moderator;
+}
+
+pub struct Admin;
+
+impl MinRole for Admin {
+    const ROLE: Role = Role::Admin;
+}
+
+/// Like [`RequireLogin`], but additionally rejects users below the role `R`
+pub struct RequireRole<R> {
+    pub login: RequireLogin,
+    pub role: Role,
+    _min: PhantomData<R>,
+}
+
+#[async_trait]
+impl<R: MinRole> FromRequestParts<BlogDrownState> for RequireRole<R> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &BlogDrownState,
+    ) -> Result<Self, Self::Rejection> {
+        let login = RequireLogin::from_request_parts(parts, state).await?;
+        let role = login.role(state).await?;
+
+        if !at_least(role, R::ROLE) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(Error::new("User lacks the role required for this endpoint")),
+            ));
+        }
+
+        Ok(Self {
+            login,
+            role,
+            _min: PhantomData,
+        })
+    }
+}
@@ -0,0 +1,260 @@
+//! RFC 6238 time based one time passwords, as used by authenticator apps.
+
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Slice, Rng, RngCore};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+/// Number of steps either side of the current one that are still accepted, to allow for clock drift
+const SKEW: i64 = 1;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Generates a new 160 bit secret, encoded as unpadded base32
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+
+    base32_encode(&secret)
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+
+    for chunk in data.chunks(5) {
+        let mut buf = [0u8; 5];
+        buf[..chunk.len()].copy_from_slice(chunk);
+
+        let bits = buf.iter().fold(0u64, |acc, b| (acc << 8) | u64::from(*b));
+        let chars = (chunk.len() * 8).div_ceil(5);
+
+        for i in 0..chars {
+            let idx = (bits >> (35 - i * 5)) & 0x1f;
+            out.push(BASE32_ALPHABET[idx as usize] as char);
+        }
+    }
+
+    out
+}
+
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let mut buffer = 0u64;
+    let mut bits = 0;
+
+    for c in s.bytes().filter(|c| *c != b'=') {
+        let val = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c.to_ascii_uppercase())?;
+
+        buffer = (buffer << 5) | val as u64;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+/// Percent encodes everything outside of the RFC 3986 unreserved set
+fn uri_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(b as char);
+        } else {
+            _ = write!(out, "%{b:02X}");
+        }
+    }
+
+    out
+}
+
+/// Builds an `otpauth://` URI suitable for rendering as a QR code
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={secret}&issuer={}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECONDS}",
+        uri_encode(issuer),
+        uri_encode(account),
+        uri_encode(issuer),
+    )
+}
+
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("hmac accepts any key size");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[19] & 0xf) as usize;
+    let code = u32::from_be_bytes(digest[offset..offset + 4].try_into().expect("slice of 4"))
+        & 0x7fff_ffff;
+
+    code % 10u32.pow(DIGITS)
+}
+
+/// Verifies a code against the secret, returning the time step it matched.
+///
+/// Steps at or before `last_step` are rejected so a code cannot be replayed.
+pub fn verify(secret: &str, code: &str, now: DateTime<Utc>, last_step: Option<i64>) -> Option<i64> {
+    let key = base32_decode(secret)?;
+
+    let code = code.trim();
+    // `parse` would also take a leading `+`
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+
+    let current = now.timestamp() / STEP_SECONDS;
+
+    (current - SKEW..=current + SKEW)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| hotp(&key, *step as u64) == code)
+}
+
+/// Generates a fresh set of single use recovery codes in the form `xxxxx-xxxxx`
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    let alphabet = Slice::new(RECOVERY_ALPHABET).expect("alphabet is not empty");
+
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut code: String = (&mut rng)
+                .sample_iter(&alphabet)
+                .take(10)
+                .map(|c| *c as char)
+                .collect();
+            code.insert(5, '-');
+            code
+        })
+        .collect()
+}
+
+/// Hashes a recovery code for storage, codes are high entropy so a fast hash suffices
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+
+    /// The RFC 4226 and RFC 6238 test secret `12345678901234567890`
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn at(timestamp: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(timestamp, 0).unwrap()
+    }
+
+    #[test]
+    fn base32_round_trips() {
+        assert_eq!(base32_encode(b"12345678901234567890"), SECRET);
+        assert_eq!(base32_decode(SECRET).unwrap(), b"12345678901234567890");
+        assert_eq!(
+            base32_decode(&SECRET.to_lowercase()).unwrap(),
+            b"12345678901234567890"
+        );
+        assert_eq!(base32_decode("MZXW6==="), Some(b"foo".to_vec()));
+        assert_eq!(base32_decode("MZXW1"), None);
+
+        let secret = generate_secret();
+        assert_eq!(secret.len(), 32);
+        assert_eq!(base32_decode(&secret).unwrap().len(), 20);
+    }
+
+    #[test]
+    fn hotp_matches_rfc_4226() {
+        let expected = [
+            755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489,
+        ];
+
+        for (counter, code) in expected.into_iter().enumerate() {
+            assert_eq!(hotp(b"12345678901234567890", counter as u64), code);
+        }
+    }
+
+    #[test]
+    fn verify_matches_rfc_6238() {
+        // the six digit suffixes of the SHA1 vectors
+        let vectors = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ];
+
+        for (timestamp, code) in vectors {
+            assert_eq!(
+                verify(SECRET, code, at(timestamp), None),
+                Some(timestamp / STEP_SECONDS)
+            );
+        }
+    }
+
+    #[test]
+    fn verify_allows_one_step_of_skew() {
+        let code = "005924";
+        let step = 1234567890 / STEP_SECONDS;
+
+        assert_eq!(verify(SECRET, code, at(1234567890 - 30), None), Some(step));
+        assert_eq!(verify(SECRET, code, at(1234567890 + 30), None), Some(step));
+        assert_eq!(verify(SECRET, code, at(1234567890 - 60), None), None);
+        assert_eq!(verify(SECRET, code, at(1234567890 + 60), None), None);
+    }
+
+    #[test]
+    fn verify_rejects_replays_and_malformed_codes() {
+        let step = 1234567890 / STEP_SECONDS;
+
+        assert_eq!(verify(SECRET, "005924", at(1234567890), Some(step)), None);
+        assert_eq!(
+            verify(SECRET, "005924", at(1234567890), Some(step - 1)),
+            Some(step)
+        );
+        assert_eq!(verify(SECRET, " 005924 ", at(1234567890), None), Some(step));
+        assert_eq!(verify(SECRET, "5924", at(1234567890), None), None);
+        assert_eq!(verify(SECRET, "0059245", at(1234567890), None), None);
+        assert_eq!(verify(SECRET, "+05924", at(1234567890), None), None);
+        assert_eq!(verify("not base32!", "005924", at(1234567890), None), None);
+    }
+
+    #[test]
+    fn recovery_codes_hash_normalized() {
+        let codes = generate_recovery_codes();
+
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes
+            .iter()
+            .all(|c| c.len() == 11 && c.as_bytes()[5] == b'-'));
+        assert_eq!(
+            hash_recovery_code("abcde-fghjk"),
+            hash_recovery_code(" ABCDEFGHJK")
+        );
+        assert_ne!(
+            hash_recovery_code("abcde-fghjk"),
+            hash_recovery_code("abcde-fghjm")
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Internal dispatch of things that happened, handlers publish an [`Event`] here
+//! instead of writing side effects such as notifications themselves.
+
+use ulid::Ulid;
+use uuid::Uuid;
+
+use crate::{
+    api::{self, notification_item, NotificationItem, UserMessage},
+    prisma::{blog_post, comment, notification, user, NotificationKind},
+    BlogDrownState,
+};
+
+#[derive(Clone, Debug)]
+pub enum Event {
+    PostCreated {
+        post_id: Ulid,
+        author: Ulid,
+    },
+    PostUpdated {
+        post_id: Ulid,
+        author: Ulid,
+    },
+    CommentCreated {
+        post_id: Ulid,
+        comment_id: Ulid,
+        author: Ulid,
+        post_owner: Ulid,
+        /// Author of the comment being replied to
+        parent_author: Option<Ulid>,
+    },
+    Followed {
+        follower: Ulid,
+        followee: Ulid,
+    },
+    Mentioned {
+        user: Ulid,
+        by: Ulid,
+        post_id: Ulid,
+        /// Unset when mentioned in the post body
+        comment_id: Option<Ulid>,
+    },
+}
+
+struct Notify {
+    user: Ulid,
+    actor: Ulid,
+    kind: NotificationKind,
+    post_id: Option<Ulid>,
+    comment_id: Option<Ulid>,
+}
+
+impl Event {
+    fn notifications(&self) -> Vec<Notify> {
+        match *self {
+            Event::PostCreated { .. } | Event::PostUpdated { .. } => vec![],
+            Event::CommentCreated {
+                post_id,
+                comment_id,
+                author,
+                post_owner,
+                parent_author,
+            } => {
+                let mut out = vec![];
+
+                if let Some(parent_author) = parent_author {
+                    out.push(Notify {
+                        user: parent_author,
+                        actor: author,
+                        kind: NotificationKind::Reply,
+                        post_id: Some(post_id),
+                        comment_id: Some(comment_id),
+                    });
+                }
+
+                // replying to the post owner already notifies them
+                if parent_author != Some(post_owner) {
+                    out.push(Notify {
+                        user: post_owner,
+                        actor: author,
+                        kind: NotificationKind::Comment,
+                        post_id: Some(post_id),
+                        comment_id: Some(comment_id),
+                    });
+                }
+
+                out
+            }
+            Event::Followed { follower, followee } => vec![Notify {
+                user: followee,
+                actor: follower,
+                kind: NotificationKind::Follow,
+                post_id: None,
+                comment_id: None,
+            }],
+            Event::Mentioned {
+                user,
+                by,
+                post_id,
+                comment_id,
+            } => vec![Notify {
+                user,
+                actor: by,
+                kind: NotificationKind::Mention,
+                post_id: Some(post_id),
+                comment_id,
+            }],
+        }
+    }
+}
+
+fn uuid(id: Ulid) -> String {
+    Uuid::from(id).to_string()
+}
+
+async fn notify(state: &BlogDrownState, notifications: Vec<Notify>) {
+    let mut notifications: Vec<_> = notifications
+        .into_iter()
+        .filter(|n| n.user != n.actor)
+        .collect();
+
+    if notifications.is_empty() {
+        return;
+    }
+
+    // recipients that muted the actor are not bothered
+    let muted = state
+        .prisma
+        .user()
+        .find_many(vec![
+            user::id::in_vec(notifications.iter().map(|n| uuid(n.user)).collect()),
+            user::muting::some(vec![user::id::in_vec(
+                notifications.iter().map(|n| uuid(n.actor)).collect(),
+            )]),
+        ])
+        .select(user::select!({ id muting: select { id } }))
+        .exec()
+        .await;
+
+    let muted = match muted {
+        Ok(muted) => muted,
+        Err(e) => {
+            tracing::error!("failed to look up muted users for notifications: {e}");
+            return;
+        }
+    };
+
+    notifications.retain(|n| {
+        !muted
+            .iter()
+            .any(|m| m.id == uuid(n.user) && m.muting.iter().any(|a| a.id == uuid(n.actor)))
+    });
+
+    let creates = notifications
+        .iter()
+        .map(|n| {
+            let mut links = vec![notification::actor::connect(user::id::equals(uuid(
+                n.actor,
+            )))];
+
+            if let Some(post_id) = n.post_id {
+                links.push(notification::post::connect(blog_post::id::equals(uuid(
+                    post_id,
+                ))));
+            }
+
+            if let Some(comment_id) = n.comment_id {
+                links.push(notification::comment::connect(comment::id::equals(uuid(
+                    comment_id,
+                ))));
+            }
+
+            state
+                .prisma
+                .notification()
+                .create(user::id::equals(uuid(n.user)), n.kind, links)
+                .select(notification_item::select())
+        })
+        .collect::<Vec<_>>();
+
+    // one round trip for all recipients, in order
+    let created = match state.prisma._batch(creates).await {
+        Ok(created) => created,
+        Err(e) => {
+            tracing::error!("failed to create notifications: {e}");
+            return;
+        }
+    };
+
+    // pushed to the recipients' open sockets as well
+    for (n, created) in notifications.iter().zip(created) {
+        state.live.publish_user(
+            vec![n.user],
+            UserMessage::Notification(NotificationItem::from(created)),
+        );
+    }
+}
+
+/// Runs every consumer of the event, failures are logged and never reach the publisher
+pub async fn dispatch(state: &BlogDrownState, event: Event) {
+    tracing::debug!("dispatching {event:?}");
+
+    notify(state, event.notifications()).await;
+    api::dispatch_webhooks(state, &event).await;
+}
@@ -0,0 +1,371 @@
+use reqwest::Url;
+use serde_derive::Deserialize;
+
+use crate::api::Error;
+
+const MAX_SUMMARY: usize = 500;
+const MAX_URL: usize = 2048;
+const MAX_TAGS: usize = 10;
+const MAX_TAG: usize = 32;
+
+/// Fields of [`FrontMatter`] and their aliases, a block needs one of these to be front matter
+const KEYS: [&str; 8] = [
+    "summary",
+    "cover_image",
+    "cover",
+    "tags",
+    "canonical_url",
+    "canonical",
+    "language",
+    "lang",
+];
+
+/// Metadata an author may put at the start of a post, between `---` lines as YAML
+/// or between `+++` lines as TOML. Keys we do not know are ignored.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct FrontMatter {
+    pub summary: Option<String>,
+    #[serde(alias = "cover")]
+    pub cover_image: Option<String>,
+    pub tags: Vec<String>,
+    #[serde(alias = "canonical")]
+    pub canonical_url: Option<String>,
+    #[serde(alias = "lang")]
+    pub language: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Yaml,
+    Toml,
+}
+
+/// The format, contents and the body after a front matter block at the very start of the text
+fn split(text: &str) -> Option<(Format, &str, &str)> {
+    let (format, delimiter) = match text.get(..3)? {
+        "---" => (Format::Yaml, "---"),
+        "+++" => (Format::Toml, "+++"),
+        _ => return None,
+    };
+
+    let rest = &text[3..];
+    let rest = rest
+        .strip_prefix("\r\n")
+        .or_else(|| rest.strip_prefix('\n'))?;
+
+    let mut offset = 0;
+
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            return Some((format, &rest[..offset], &rest[offset + line.len()..]));
+        }
+
+        offset += line.len();
+    }
+
+    None
+}
+
+fn http_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https") && u.has_host())
+}
+
+/// `en`, `pt-BR` or `zh-Hant-TW`
+fn language_tag(tag: &str) -> bool {
+    let mut parts = tag.split('-');
+
+    parts
+        .next()
+        .is_some_and(|p| (2..=3).contains(&p.len()) && p.chars().all(|c| c.is_ascii_alphabetic()))
+        && parts.all(|p| (1..=8).contains(&p.len()) && p.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// The trimmed value, unset when blank
+fn present(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_owned()).filter(|v| !v.is_empty())
+}
+
+impl FrontMatter {
+    /// Checks and normalizes each field, tags become lowercase with `-` for spaces
+    fn validate(self) -> Result<Self, Error> {
+        let mut err = Error::new("Invalid front matter");
+
+        let summary = present(self.summary);
+        if summary
+            .as_ref()
+            .is_some_and(|s| s.chars().count() > MAX_SUMMARY)
+        {
+            err.add(
+                "summary",
+                format!("Must be at most {MAX_SUMMARY} characters"),
+            );
+        }
+
+        // relative to us, such as an upload under /media, or on the web
+        let cover_image = present(self.cover_image);
+        if cover_image.as_ref().is_some_and(|c| {
+            c.len() > MAX_URL || !((c.starts_with('/') && !c.starts_with("//")) || http_url(c))
+        }) {
+            err.add(
+                "cover_image",
+                "Must be a path on this site or an http(s) URL",
+            );
+        }
+
+        let canonical_url = present(self.canonical_url);
+        if canonical_url
+            .as_ref()
+            .is_some_and(|c| c.len() > MAX_URL || !http_url(c))
+        {
+            err.add("canonical_url", "Must be an http(s) URL");
+        }
+
+        let language = present(self.language);
+        if language
+            .as_ref()
+            .is_some_and(|l| l.len() > 35 || !language_tag(l))
+        {
+            err.add("language", "Must be a language tag such as en or pt-BR");
+        }
+
+        let mut tags: Vec<String> = vec![];
+
+        for tag in self.tags {
+            let tag = tag
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join("-")
+                .to_lowercase();
+
+            if tag.is_empty() || tag.chars().count() > MAX_TAG {
+                err.add("tags", format!("Tags must be 1 to {MAX_TAG} characters"));
+            } else if !tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_'))
+            {
+                err.add("tags", "Tags may only contain letters, digits, - and _");
+            } else if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        if tags.len() > MAX_TAGS {
+            err.add("tags", format!("At most {MAX_TAGS} tags are allowed"));
+        }
+
+        if !err.errors.is_empty() {
+            return Err(err);
+        }
+
+        Ok(Self {
+            summary,
+            cover_image,
+            tags,
+            canonical_url,
+            language,
+        })
+    }
+}
+
+enum Block {
+    Yaml(serde_norway::Value),
+    Toml(toml::Value),
+}
+
+/// The front matter block at the start of the text and the body after it.
+///
+/// Blocks without any key we know are left in the body, so a `---` block is only taken
+/// for front matter when it is a YAML mapping with one of them, otherwise it is a post
+/// starting with a rule. `+++` never starts markdown so any TOML errors are reported.
+fn recognize(text: &str) -> Result<Option<(Block, &str)>, String> {
+    let Some((format, raw, body)) = split(text) else {
+        return Ok(None);
+    };
+
+    let block = match format {
+        Format::Yaml => match serde_norway::from_str(raw) {
+            Ok(serde_norway::Value::Mapping(mapping))
+                if KEYS.iter().any(|key| mapping.contains_key(key)) =>
+            {
+                Block::Yaml(serde_norway::Value::Mapping(mapping))
+            }
+            _ => return Ok(None),
+        },
+        Format::Toml => {
+            let table = toml::from_str::<toml::Table>(raw).map_err(|e| e.message().to_owned())?;
+
+            if !KEYS.iter().any(|key| table.contains_key(*key)) {
+                return Ok(None);
+            }
+
+            Block::Toml(toml::Value::Table(table))
+        }
+    };
+
+    Ok(Some((block, body)))
+}
+
+/// The validated front matter of a post body, empty when the body has none
+pub fn parse(text: &str) -> Result<FrontMatter, Error> {
+    let invalid = |e: String| {
+        let mut err = Error::new("Invalid front matter");
+        err.add("front_matter", e);
+        err
+    };
+
+    let front_matter: FrontMatter = match recognize(text).map_err(invalid)? {
+        None => return Ok(FrontMatter::default()),
+        Some((Block::Yaml(value), _)) => serde_norway::from_value(value).map_err(|e| e.to_string()),
+        Some((Block::Toml(value), _)) => value.try_into().map_err(|e| e.message().to_owned()),
+    }
+    .map_err(invalid)?;
+
+    front_matter.validate()
+}
+
+/// The body without its front matter
+pub fn strip(text: &str) -> &str {
+    match recognize(text) {
+        Ok(Some((_, body))) => body,
+        _ => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(text: &str) -> Vec<String> {
+        let mut fields: Vec<_> = parse(text).unwrap_err().errors.into_keys().collect();
+        fields.sort();
+        fields
+    }
+
+    #[test]
+    fn splits_blocks() {
+        let Some((Format::Yaml, raw, body)) = split("---\nsummary: x\n---\nbody\n") else {
+            panic!("yaml block not found");
+        };
+        assert_eq!((raw, body), ("summary: x\n", "body\n"));
+
+        let Some((Format::Toml, raw, body)) = split("+++\r\ntags = []\r\n+++  \r\nbody") else {
+            panic!("toml block not found");
+        };
+        assert_eq!((raw, body), ("tags = []\r\n", "body"));
+
+        let Some((_, raw, body)) = split("---\n---") else {
+            panic!("empty block not found");
+        };
+        assert_eq!((raw, body), ("", ""));
+    }
+
+    #[test]
+    fn needs_delimiters_on_their_own_lines() {
+        assert!(split("---summary: x\n---\n").is_none());
+        assert!(split("----\nsummary: x\n----\n").is_none());
+        assert!(split("---\nsummary: x\n").is_none());
+        assert!(split("---\nsummary: x\n+++\n").is_none());
+        assert!(split(" ---\nsummary: x\n---\n").is_none());
+        assert!(split("").is_none());
+    }
+
+    #[test]
+    fn parses_yaml_and_toml() {
+        let yaml = parse("---\nsummary: Hi\ncover: /media/a/medium\ntags: [Rust]\nlang: en\n---\n")
+            .unwrap();
+        assert_eq!(yaml.summary.as_deref(), Some("Hi"));
+        assert_eq!(yaml.cover_image.as_deref(), Some("/media/a/medium"));
+        assert_eq!(yaml.tags, ["rust"]);
+        assert_eq!(yaml.language.as_deref(), Some("en"));
+
+        let toml = parse("+++\ncanonical = \"https://example.com/a\"\n+++\nbody").unwrap();
+        assert_eq!(toml.canonical_url.as_deref(), Some("https://example.com/a"));
+    }
+
+    #[test]
+    fn leaves_posts_starting_with_a_rule() {
+        let text = "---\nJust a paragraph between rules\n---\n";
+
+        assert!(parse(text).unwrap().summary.is_none());
+        assert_eq!(strip(text), text);
+        assert_eq!(strip("---\nsummary: x\n---\nbody"), "body");
+    }
+
+    #[test]
+    fn needs_a_known_key() {
+        for text in [
+            "---\nNote: this is a mapping\n---\nbody",
+            "---\n---\nbody",
+            "+++\ntitle = \"Hugo\"\n+++\nbody",
+        ] {
+            assert!(parse(text).unwrap().tags.is_empty());
+            assert_eq!(strip(text), text);
+        }
+
+        let text = "---\ntitle: ignored\ntags: [a]\n---\nbody";
+        assert_eq!(parse(text).unwrap().tags, ["a"]);
+        assert_eq!(strip(text), "body");
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        assert_eq!(errors("+++\nnot toml\n+++\n"), ["front_matter"]);
+        assert_eq!(errors("---\ntags: 3\n---\n"), ["front_matter"]);
+    }
+
+    #[test]
+    fn normalizes_tags() {
+        let front_matter =
+            parse("---\ntags: [Rust Lang, rust-lang, Web,  spaced   out ]\n---\n").unwrap();
+
+        assert_eq!(front_matter.tags, ["rust-lang", "web", "spaced-out"]);
+    }
+
+    #[test]
+    fn rejects_invalid_tags() {
+        assert_eq!(errors("---\ntags: [c++]\n---\n"), ["tags"]);
+        assert_eq!(errors("---\ntags: [\" \"]\n---\n"), ["tags"]);
+
+        let many = (0..=MAX_TAGS).map(|i| format!("t{i}")).collect::<Vec<_>>();
+        assert_eq!(
+            errors(&format!("---\ntags: [{}]\n---\n", many.join(", "))),
+            ["tags"]
+        );
+    }
+
+    #[test]
+    fn checks_urls() {
+        assert!(parse("---\ncover_image: https://example.com/a.png\n---\n").is_ok());
+
+        for cover in ["//evil.example/a.png", "javascript:alert(1)", "media/a.png"] {
+            assert_eq!(
+                errors(&format!("---\ncover_image: \"{cover}\"\n---\n")),
+                ["cover_image"]
+            );
+        }
+
+        for canonical in ["/a", "ftp://example.com/a", "https://"] {
+            assert_eq!(
+                errors(&format!("---\ncanonical_url: \"{canonical}\"\n---\n")),
+                ["canonical_url"]
+            );
+        }
+    }
+
+    #[test]
+    fn checks_language_and_summary() {
+        assert!(parse("---\nlanguage: zh-Hant-TW\n---\n").is_ok());
+        assert_eq!(errors("---\nlanguage: english!\n---\n"), ["language"]);
+        assert_eq!(errors("---\nlanguage: e\n---\n"), ["language"]);
+
+        let blank = parse("---\nsummary: \"  \"\n---\n").unwrap();
+        assert!(blank.summary.is_none());
+
+        let long = "a".repeat(MAX_SUMMARY + 1);
+        assert_eq!(
+            errors(&format!("---\nsummary: {long}\nlanguage: '!'\n---\n")),
+            ["language", "summary"]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::sync::LazyLock;
+
+use syntect::{
+    highlighting::ThemeSet,
+    html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+/// Prefix of every class we emit, so the stylesheets cannot collide with the frontends' own
+pub const CLASS_PREFIX: &str = "hl-";
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed {
+    prefix: CLASS_PREFIX,
+};
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+/// Stylesheets of the bundled themes by slug, in the order they are listed
+static THEMES: LazyLock<Vec<(String, String)>> = LazyLock::new(|| {
+    let mut themes = ThemeSet::load_defaults()
+        .themes
+        .into_iter()
+        .filter_map(|(name, theme)| {
+            let css = css_for_theme_with_class_style(&theme, CLASS_STYLE)
+                .inspect_err(|e| tracing::error!("failed to build stylesheet for {name}: {e}"))
+                .ok()?;
+
+            Some((slug(&name), css))
+        })
+        .collect::<Vec<_>>();
+
+    themes.sort();
+    themes
+});
+
+/// `Solarized (dark)` becomes `solarized-dark`
+fn slug(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The language of a fenced code block's info string, such as `rust` in `rust,ignore`
+pub fn language(info: &str) -> &str {
+    info.split(|c: char| c.is_whitespace() || c == ',')
+        .next()
+        .unwrap_or_default()
+}
+
+pub fn supports(language: &str) -> bool {
+    !language.is_empty() && SYNTAXES.find_syntax_by_token(language).is_some()
+}
+
+/// A `<pre>` of the code with each token in a `<span>` classed by its scope, `None` for
+/// languages we have no syntax for. This is slow for long code and should run on a blocking thread.
+pub fn highlight(language: &str, code: &str) -> Option<String> {
+    let syntax = SYNTAXES.find_syntax_by_token(language)?;
+
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, CLASS_STYLE);
+
+    for line in LinesWithEndings::from(code) {
+        if let Err(e) = generator.parse_html_for_line_which_includes_newline(line) {
+            tracing::warn!("failed to highlight {language} code: {e}");
+            return None;
+        }
+    }
+
+    let class = language
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+' | '#'))
+        .collect::<String>();
+
+    Some(format!(
+        "<pre><code class=\"language-{class} {CLASS_PREFIX}code\">{}</code></pre>\n",
+        generator.finalize()
+    ))
+}
+
+/// Slugs of the themes a stylesheet is served for
+pub fn themes() -> impl Iterator<Item = &'static str> {
+    THEMES.iter().map(|(slug, _)| slug.as_str())
+}
+
+pub fn stylesheet(theme: &str) -> Option<&'static str> {
+    THEMES
+        .iter()
+        .find(|(slug, _)| slug == theme)
+        .map(|(_, css)| css.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_language_from_info_string() {
+        assert_eq!(language("rust,ignore"), "rust");
+        assert_eq!(language("python title=x.py"), "python");
+        assert_eq!(language(""), "");
+        assert!(supports("rs"));
+        assert!(!supports(""));
+        assert!(!supports("nosuchlanguage"));
+    }
+
+    #[test]
+    fn classes_tokens_with_prefix() {
+        let html = highlight("rust", "let x = \"<b>\";\n").unwrap();
+
+        assert!(html.starts_with("<pre><code class=\"language-rust hl-code\">"));
+        assert!(html.contains("&lt;b&gt;"), "{html}");
+        assert!(html
+            .split("class=\"")
+            .skip(1)
+            .all(|rest| rest.starts_with("language-") || rest.starts_with(CLASS_PREFIX)));
+    }
+
+    #[test]
+    fn strips_unexpected_characters_from_class() {
+        let html = highlight("c++", "int x;\n").unwrap();
+        assert!(html.starts_with("<pre><code class=\"language-c++ hl-code\">"));
+
+        assert!(highlight("nosuchlanguage", "x").is_none());
+    }
+
+    #[test]
+    fn serves_theme_stylesheets() {
+        assert_eq!(slug("Solarized (dark)"), "solarized-dark");
+        assert!(themes().any(|t| t == "solarized-dark"));
+        assert!(stylesheet("solarized-dark").is_some_and(|css| css.contains(".hl-")));
+        assert!(stylesheet("nope").is_none());
+    }
+}
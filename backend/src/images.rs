@@ -0,0 +1,357 @@
+use std::io::Cursor;
+
+use image::{
+    codecs::{
+        avif::AvifEncoder,
+        jpeg::JpegEncoder,
+        png::{CompressionType, FilterType as PngFilter, PngEncoder},
+        webp::WebPEncoder,
+    },
+    error::{DecodingError, LimitError, LimitErrorKind},
+    imageops::FilterType,
+    DynamicImage, ImageError, ImageFormat, ImageReader, ImageResult, Limits,
+};
+
+use crate::prisma::UploadVariantKind;
+
+/// Variants made of images larger than the given bounding box, smaller ones are served as is
+pub const VARIANTS: [(UploadVariantKind, u32); 2] = [
+    (UploadVariantKind::Thumbnail, 320),
+    (UploadVariantKind::Medium, 1280),
+];
+
+/// Largest width or height we decode, anything bigger is rejected before allocating it
+const MAX_DIMENSION: u32 = 16384;
+
+/// Largest width times height we accept, decoding this takes about 160 MB
+const MAX_PIXELS: u64 = 40_000_000;
+
+/// GIF application extensions that only control playback, others carry metadata such as XMP
+const GIF_PLAYBACK: [&[u8]; 2] = [b"NETSCAPE2.0", b"ANIMEXTS1.0"];
+
+pub struct Encoded {
+    pub data: Vec<u8>,
+    /// File extension, which storage keys and served content types are based on
+    pub ext: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct Processed {
+    pub original: Encoded,
+    pub variants: Vec<(UploadVariantKind, Encoded)>,
+}
+
+/// Whether uploads with the extension go through [`process`]
+pub fn processable(ext: &str) -> bool {
+    matches!(ext, "png" | "jpg" | "webp")
+}
+
+fn encode(img: &DynamicImage, format: ImageFormat) -> ImageResult<Encoded> {
+    let mut data = vec![];
+
+    let ext = match format {
+        ImageFormat::Png => {
+            img.write_with_encoder(PngEncoder::new_with_quality(
+                &mut data,
+                CompressionType::Best,
+                PngFilter::Adaptive,
+            ))?;
+            "png"
+        }
+        ImageFormat::Jpeg => {
+            // jpeg has no alpha channel, sources that do are never encoded as jpeg
+            DynamicImage::ImageRgb8(img.to_rgb8())
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut data, 85))?;
+            "jpg"
+        }
+        ImageFormat::WebP => {
+            img.write_with_encoder(WebPEncoder::new_lossless(&mut data))?;
+            "webp"
+        }
+        ImageFormat::Avif => {
+            img.write_with_encoder(AvifEncoder::new_with_speed_quality(&mut data, 8, 80))?;
+            "avif"
+        }
+        _ => unreachable!("only formats we serve are encoded"),
+    };
+
+    Ok(Encoded {
+        data,
+        ext,
+        width: img.width(),
+        height: img.height(),
+    })
+}
+
+fn check_pixels((width, height): (u32, u32)) -> ImageResult<()> {
+    if u64::from(width) * u64::from(height) > MAX_PIXELS {
+        return Err(ImageError::Limits(LimitError::from_kind(
+            LimitErrorKind::DimensionError,
+        )));
+    }
+
+    Ok(())
+}
+
+/// Encodes in the source format, WebP and AVIF and keeps the smallest
+fn encode_smallest(img: &DynamicImage, source: ImageFormat) -> ImageResult<Encoded> {
+    let mut smallest = encode(img, source)?;
+
+    for format in [ImageFormat::WebP, ImageFormat::Avif] {
+        if format == source {
+            continue;
+        }
+
+        let candidate = encode(img, format)?;
+
+        if candidate.data.len() < smallest.data.len() {
+            smallest = candidate;
+        }
+    }
+
+    Ok(smallest)
+}
+
+/// Decodes the image and encodes it again along with its resized variants.
+///
+/// Nothing but pixels survives decoding, so this drops all EXIF, GPS and other metadata,
+/// the EXIF orientation is applied to the pixels first so photos stay upright.
+/// This is slow and should run on a blocking thread.
+pub fn process(data: &[u8]) -> ImageResult<Processed> {
+    let mut reader = ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+
+    let source = reader.format().unwrap_or(ImageFormat::Png);
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    reader.limits(limits);
+
+    let mut decoder = reader.into_decoder()?;
+    check_pixels(decoder.dimensions())?;
+
+    let orientation = decoder.orientation()?;
+
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+
+    // encoders disagree on the higher bit depths, eight bits is what browsers show anyway
+    let img = if img.color().has_alpha() {
+        DynamicImage::ImageRgba8(img.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(img.to_rgb8())
+    };
+
+    let source = match source {
+        ImageFormat::Jpeg if img.color().has_alpha() => ImageFormat::Png,
+        ImageFormat::Jpeg | ImageFormat::WebP => source,
+        _ => ImageFormat::Png,
+    };
+
+    let mut variants = vec![];
+
+    for (kind, size) in VARIANTS {
+        if img.width() <= size && img.height() <= size {
+            continue;
+        }
+
+        let resized = img.resize(size, size, FilterType::Lanczos3);
+
+        variants.push((kind, encode_smallest(&resized, source)?));
+    }
+
+    Ok(Processed {
+        original: encode_smallest(&img, source)?,
+        variants,
+    })
+}
+
+/// Splits GIF data into the blocks it is made of
+struct GifReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> GifReader<'a> {
+    fn take(&mut self, n: usize) -> ImageResult<&'a [u8]> {
+        let Some((taken, rest)) = self.data.split_at_checked(n) else {
+            return Err(ImageError::Decoding(DecodingError::new(
+                ImageFormat::Gif.into(),
+                "unexpected end of file",
+            )));
+        };
+
+        self.data = rest;
+
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> ImageResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// A color table, present when the high bit of `flags` is set
+    fn color_table(&mut self, flags: u8) -> ImageResult<&'a [u8]> {
+        let len = if flags & 0x80 != 0 {
+            3 << ((flags & 0x07) + 1)
+        } else {
+            0
+        };
+
+        self.take(len)
+    }
+
+    /// Data sub-blocks up to and including the empty one ending them
+    fn sub_blocks(&mut self) -> ImageResult<&'a [u8]> {
+        let start = self.data;
+        let mut len = 0;
+
+        loop {
+            let size = usize::from(self.byte()?);
+            self.take(size)?;
+            len += size + 1;
+
+            if size == 0 {
+                return Ok(&start[..len]);
+            }
+        }
+    }
+}
+
+/// Copies the GIF without its comments and application extensions other than the loop count,
+/// which is where XMP and other metadata is kept.
+///
+/// Frames are copied as they are to keep the animation, anything after the trailer is dropped.
+pub fn strip_gif(data: &[u8]) -> ImageResult<Encoded> {
+    let malformed =
+        |reason: &str| ImageError::Decoding(DecodingError::new(ImageFormat::Gif.into(), reason));
+
+    let mut reader = GifReader { data };
+    let mut out = vec![];
+
+    let header = reader.take(6)?;
+    if header != b"GIF87a" && header != b"GIF89a" {
+        return Err(malformed("not a GIF"));
+    }
+
+    let screen = reader.take(7)?;
+    let width = u32::from(u16::from_le_bytes([screen[0], screen[1]]));
+    let height = u32::from(u16::from_le_bytes([screen[2], screen[3]]));
+    check_pixels((width, height))?;
+
+    out.extend_from_slice(header);
+    out.extend_from_slice(screen);
+    out.extend_from_slice(reader.color_table(screen[4])?);
+
+    loop {
+        match reader.byte()? {
+            // extension
+            0x21 => {
+                let label = reader.byte()?;
+                let blocks = reader.sub_blocks()?;
+
+                let keep = match label {
+                    0xfe => false,
+                    // the first sub-block holds the application identifier
+                    0xff => blocks
+                        .get(1..12)
+                        .is_some_and(|id| blocks[0] == 11 && GIF_PLAYBACK.contains(&id)),
+                    _ => true,
+                };
+
+                if keep {
+                    out.extend_from_slice(&[0x21, label]);
+                    out.extend_from_slice(blocks);
+                }
+            }
+            // image
+            0x2c => {
+                let descriptor = reader.take(9)?;
+
+                out.push(0x2c);
+                out.extend_from_slice(descriptor);
+                out.extend_from_slice(reader.color_table(descriptor[8])?);
+                // minimum code size of the compressed pixels
+                out.push(reader.byte()?);
+                out.extend_from_slice(reader.sub_blocks()?);
+            }
+            // trailer
+            0x3b => {
+                out.push(0x3b);
+                break;
+            }
+            _ => return Err(malformed("unknown block")),
+        }
+    }
+
+    Ok(Encoded {
+        data: out,
+        ext: "gif",
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1x1 GIF with a single frame and the given extensions before it
+    fn gif(extensions: &[&[u8]]) -> Vec<u8> {
+        let mut data = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff".to_vec();
+
+        for extension in extensions {
+            data.extend_from_slice(extension);
+        }
+
+        data.extend_from_slice(b"\x2c\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02\x44\x01\x00\x3b");
+        data
+    }
+
+    const LOOP: &[u8] = b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00";
+    const XMP: &[u8] = b"\x21\xff\x0bXMP DataXMP\x05<x:/>\x00";
+    const COMMENT: &[u8] = b"\x21\xfe\x06secret\x00";
+    const FRAME_DELAY: &[u8] = b"\x21\xf9\x04\x00\x0a\x00\x00\x00";
+
+    #[test]
+    fn keeps_frames_and_playback() {
+        let data = gif(&[LOOP, FRAME_DELAY]);
+        let stripped = strip_gif(&data).unwrap();
+
+        assert_eq!(stripped.data, data);
+        assert_eq!((stripped.width, stripped.height), (1, 1));
+    }
+
+    #[test]
+    fn strips_metadata() {
+        let stripped = strip_gif(&gif(&[COMMENT, LOOP, XMP, FRAME_DELAY])).unwrap();
+
+        assert_eq!(stripped.data, gif(&[LOOP, FRAME_DELAY]));
+    }
+
+    #[test]
+    fn drops_data_after_trailer() {
+        let mut data = gif(&[]);
+        data.extend_from_slice(b"hidden");
+
+        assert_eq!(strip_gif(&data).unwrap().data, gif(&[]));
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let data = gif(&[LOOP]);
+
+        assert!(strip_gif(&data[..data.len() - 1]).is_err());
+        assert!(strip_gif(&data[..20]).is_err());
+        assert!(strip_gif(b"GIF89a").is_err());
+        assert!(strip_gif(&gif(&[b"\x99"])).is_err());
+    }
+
+    #[test]
+    fn rejects_too_many_pixels() {
+        let mut data = gif(&[]);
+        data[6..10].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+
+        assert!(strip_gif(&data).is_err());
+    }
+}
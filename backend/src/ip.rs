@@ -0,0 +1,191 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, StatusCode},
+    Json,
+};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+use crate::{
+    api::{ApiError, Error},
+    BlogDrownState,
+};
+
+/// The address of the connecting client.
+///
+/// When running behind a reverse proxy (`BLOGDROWN_TRUST_PROXY`) this is the last hop
+/// recorded in `X-Forwarded-For`, as that is the one appended by our own proxy.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl FromRequestParts<BlogDrownState> for ClientIp {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &BlogDrownState,
+    ) -> Result<Self, Self::Rejection> {
+        if state.trust_proxy {
+            let forwarded = parts
+                .headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|h| h.to_str().ok())
+                .flat_map(|h| h.split(','))
+                .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+                .next_back();
+
+            if let Some(ip) = forwarded {
+                return Ok(Self(ip));
+            }
+        }
+
+        let Some(ConnectInfo(addr)) = parts.extensions.get::<ConnectInfo<SocketAddr>>() else {
+            tracing::error!("ConnectInfo missing, server was not started with connect info");
+
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(Error::new("Could not determine client address")),
+            ));
+        };
+
+        Ok(Self(addr.ip().to_canonical()))
+    }
+}
+
+/// Whether the address is routable on the public internet, so outgoing requests to it
+/// cannot reach services on our own network
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // "this network", may reach the local host
+                || a == 0
+                // shared address space used by carrier-grade NAT
+                || (a == 100 && (b & 0xc0) == 64)
+                // benchmarking
+                || (a == 198 && (b & 0xfe) == 18)
+                // reserved, including broadcast
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            let first = segments[0];
+
+            let octets = ip.octets();
+
+            // NAT64 and 6to4 addresses are routed to the IPv4 address they embed
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., a, b, c, d] = octets;
+                return is_public(IpAddr::from([a, b, c, d]));
+            }
+
+            if first == 0x2002 {
+                let [_, _, a, b, c, d, ..] = octets;
+                return is_public(IpAddr::from([a, b, c, d]));
+            }
+
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // unique local and link local ranges
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Resolves hosts to public addresses only, so outgoing requests such as webhooks
+/// cannot be pointed at our own network, even by a host that later changes its records
+pub struct PublicOnly;
+
+impl Resolve for PublicOnly {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<_> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(
+                    format!("{} does not resolve to a public address", name.as_str()).into(),
+                );
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(addr: &str) -> bool {
+        is_public(addr.parse().unwrap())
+    }
+
+    #[test]
+    fn allows_public_addresses() {
+        assert!(public("93.184.215.14"));
+        assert!(public("2606:2800:21f:cb07:6820:80da:af6b:8b2c"));
+    }
+
+    #[test]
+    fn rejects_special_ipv4_ranges() {
+        for addr in [
+            "0.1.2.3",
+            "10.0.0.1",
+            "100.64.0.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "172.16.0.1",
+            "192.168.1.1",
+            "198.18.0.1",
+            "198.19.255.255",
+            "240.0.0.1",
+            "255.255.255.255",
+        ] {
+            assert!(!public(addr), "{addr}");
+        }
+
+        assert!(public("198.20.0.1"));
+    }
+
+    #[test]
+    fn rejects_special_ipv6_ranges() {
+        for addr in [
+            "::",
+            "::1",
+            "fc00::1",
+            "fe80::1",
+            "ff02::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!public(addr), "{addr}");
+        }
+    }
+
+    #[test]
+    fn checks_embedded_ipv4() {
+        assert!(!public("64:ff9b::7f00:1"));
+        assert!(!public("64:ff9b::a9fe:a9fe"));
+        assert!(!public("2002:c0a8:101::1"));
+        assert!(!public("2002:7f00:1::"));
+
+        assert!(public("64:ff9b::5db8:d70e"));
+        assert!(public("2002:5db8:d70e::1"));
+    }
+}
@@ -0,0 +1,232 @@
+//! In-process hub fanning out changes to clients following them live
+
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+};
+
+use axum::response::sse::Event;
+use tokio::sync::broadcast;
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
+use ulid::{Generator, Ulid};
+
+use crate::api::{PostUpdate, UserMessage};
+
+/// Changes buffered per subscriber before it lags behind and has to resync
+const CAPACITY: usize = 256;
+
+/// User events kept around for reconnecting clients to resume from
+const BACKLOG: usize = 1024;
+
+pub struct PostChange {
+    /// Author of the comment that changed, unset for changes to the post itself
+    pub comment_author: Option<Ulid>,
+    pub update: PostUpdate,
+}
+
+/// Channels of the posts that currently have followers
+type PostChannels = Arc<Mutex<HashMap<Ulid, broadcast::Sender<Arc<PostChange>>>>>;
+
+/// Changes to one post, ending after the post is removed
+struct PostFollower {
+    post_id: Ulid,
+    channels: PostChannels,
+    changes: BroadcastStream<Arc<PostChange>>,
+    removed: bool,
+}
+
+impl Stream for PostFollower {
+    type Item = Result<Arc<PostChange>, BroadcastStreamRecvError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.removed {
+            return Poll::Ready(None);
+        }
+
+        let next = ready!(Pin::new(&mut self.changes).poll_next(cx));
+
+        if let Some(Ok(change)) = &next {
+            self.removed = matches!(change.update, PostUpdate::PostRemoved(_));
+        }
+
+        Poll::Ready(next)
+    }
+}
+
+impl Drop for PostFollower {
+    fn drop(&mut self) {
+        let mut channels = self.channels.lock().expect("post channels lock poisoned");
+
+        // our receiver is only dropped after this, so one left means nobody else follows the post
+        if channels
+            .get(&self.post_id)
+            .is_some_and(|sender| sender.receiver_count() <= 1)
+        {
+            channels.remove(&self.post_id);
+        }
+    }
+}
+
+pub struct UserEvent {
+    /// Increasing for the lifetime of the process, used to resume after reconnecting
+    pub id: Ulid,
+    pub users: Vec<Ulid>,
+    pub message: UserMessage,
+}
+
+struct Backlog {
+    ids: Generator,
+    recent: VecDeque<Arc<UserEvent>>,
+    /// Newest event that no longer fits the backlog
+    evicted: Option<Ulid>,
+}
+
+pub struct UserSubscription {
+    /// The events since the given id are unknown, the client should fetch its state again
+    pub resync: bool,
+    pub missed: Vec<Arc<UserEvent>>,
+    pub events: broadcast::Receiver<Arc<UserEvent>>,
+}
+
+#[derive(Clone)]
+pub struct Hub {
+    posts: PostChannels,
+    users: broadcast::Sender<Arc<UserEvent>>,
+    backlog: Arc<Mutex<Backlog>>,
+    started: Ulid,
+}
+
+impl fmt::Debug for Hub {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hub")
+            .field("started", &self.started)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Hub {
+    fn default() -> Self {
+        Self {
+            posts: PostChannels::default(),
+            users: broadcast::channel(CAPACITY).0,
+            backlog: Arc::new(Mutex::new(Backlog {
+                ids: Generator::new(),
+                recent: VecDeque::with_capacity(BACKLOG),
+                evicted: None,
+            })),
+            started: Ulid::new(),
+        }
+    }
+}
+
+impl Hub {
+    /// Sends the change to everyone following the post, nobody listening is not an error
+    pub fn publish(&self, post_id: Ulid, comment_author: Option<Ulid>, update: PostUpdate) {
+        let channels = self.posts.lock().expect("post channels lock poisoned");
+
+        if let Some(sender) = channels.get(&post_id) {
+            _ = sender.send(Arc::new(PostChange {
+                comment_author,
+                update,
+            }));
+        }
+    }
+
+    /// Server-sent events for one post, comments by `muted` authors are left out.
+    ///
+    /// The stream ends once the post is hidden or deleted, as its visibility was only checked up front.
+    pub fn follow_post(
+        &self,
+        post_id: Ulid,
+        muted: Vec<Ulid>,
+    ) -> impl Stream<Item = Result<Event, Infallible>> {
+        let changes = self
+            .posts
+            .lock()
+            .expect("post channels lock poisoned")
+            .entry(post_id)
+            .or_insert_with(|| broadcast::channel(CAPACITY).0)
+            .subscribe();
+
+        let follower = PostFollower {
+            post_id,
+            channels: self.posts.clone(),
+            changes: BroadcastStream::new(changes),
+            removed: false,
+        };
+
+        follower.filter_map(move |change| match change {
+            Ok(change) if !change.comment_author.is_some_and(|a| muted.contains(&a)) => {
+                let event = Event::default().event(change.update.name());
+
+                Some(Ok(event
+                    .json_data(&change.update)
+                    .expect("PostUpdate serializes to json")))
+            }
+            Ok(_) => None,
+            // the client missed changes and should fetch the post again
+            Err(BroadcastStreamRecvError::Lagged(_)) => {
+                Some(Ok(Event::default().event("resync").data("")))
+            }
+        })
+    }
+
+    /// Sends the message to the connected sessions of `users`
+    pub fn publish_user(&self, users: Vec<Ulid>, message: UserMessage) {
+        if users.is_empty() {
+            return;
+        }
+
+        let mut backlog = self.backlog.lock().expect("backlog lock poisoned");
+
+        let id = backlog.ids.generate().unwrap_or_else(|_| Ulid::new());
+        let event = Arc::new(UserEvent { id, users, message });
+
+        if backlog.recent.len() == BACKLOG {
+            backlog.evicted = backlog.recent.pop_front().map(|e| e.id);
+        }
+
+        backlog.recent.push_back(event.clone());
+
+        // sending under the lock keeps the backlog and live events in the same order
+        _ = self.users.send(event);
+    }
+
+    /// Events for one user, starting after `last_seen` when resuming a connection
+    pub fn follow_user(&self, user: Ulid, last_seen: Option<Ulid>) -> UserSubscription {
+        let backlog = self.backlog.lock().expect("backlog lock poisoned");
+
+        let events = self.users.subscribe();
+
+        let Some(last_seen) = last_seen else {
+            return UserSubscription {
+                resync: false,
+                missed: vec![],
+                events,
+            };
+        };
+
+        let resync =
+            last_seen < self.started || backlog.evicted.is_some_and(|evicted| last_seen < evicted);
+
+        let missed = backlog
+            .recent
+            .iter()
+            .filter(|e| e.id > last_seen && e.users.contains(&user))
+            .cloned()
+            .collect();
+
+        UserSubscription {
+            resync,
+            missed,
+            events,
+        }
+    }
+}
@@ -1,10 +1,12 @@
 #![allow(dead_code)]
 
 use core::error;
-use std::{env, sync::Arc, time::Duration};
+use std::{env, net::SocketAddr, sync::Arc, time::Duration};
 
+use chrono::TimeDelta;
 use hmac::{Hmac, Mac};
 use sha2::Sha384;
+use tokio::sync::Semaphore;
 use tower_http::{
     cors::{Any, CorsLayer},
     services::{ServeDir, ServeFile},
@@ -17,9 +19,23 @@ use tower_http::{
 mod prisma;
 
 mod api;
+mod audit;
 mod auth;
 mod bounded;
+mod events;
+mod front_matter;
+mod highlight;
+mod images;
+mod ip;
+mod live;
 mod logger;
+mod mail;
+mod markdown;
+mod ratelimit;
+mod storage;
+
+/// Longest grace period before a scheduled account deletion, about ten years
+const MAX_DELETION_GRACE_DAYS: u32 = 3650;
 
 #[derive(Clone, Debug)]
 struct BlogDrownState {
@@ -27,12 +43,36 @@ struct BlogDrownState {
     jwt_secret: Hmac<Sha384>,
     production: bool,
     sim_latency: Option<Duration>,
+    trust_proxy: bool,
+    limits: Arc<ratelimit::Limits>,
+    deletion_grace: TimeDelta,
+    registration: auth::Registration,
+    /// Invite uses each non-admin user may hand out
+    invite_quota: i64,
+    /// Emoji users may react with
+    reactions: Arc<Vec<String>>,
+    live: live::Hub,
+    /// Client for outgoing webhook deliveries
+    http: reqwest::Client,
+    /// Unset when no SMTP server is configured, features that send mail are then unavailable
+    mailer: Option<Arc<mail::Mailer>>,
+    /// Other sites allowed to make credentialed requests, from `BLOGDROWN_CORS_ORIGINS`
+    cors_origins: Arc<Vec<HeaderValue>>,
+    storage: Arc<dyn storage::Storage>,
+    /// Largest file a single upload may be, in bytes
+    upload_limit: usize,
+    /// Bytes each user's uploads may take up in total
+    upload_quota: i64,
+    /// Images decoded at once, each may take hundreds of megabytes while processing
+    image_permits: Arc<Semaphore>,
+    /// Characters of post excerpts in listings
+    excerpt_length: usize,
 }
 
 use axum::Router;
 use axum::{
     extract::{Request, State},
-    http::Method,
+    http::{header, HeaderName, HeaderValue, Method},
     middleware::Next,
     response::Response,
 };
@@ -78,6 +118,34 @@ async fn main() -> Result<(), Box<dyn error::Error>> {
 
     let client = PrismaClient::_builder().build().await?;
 
+    let production = env::var("BLOGDROWN_DEV")
+        .map_or(true, |s| !matches!(s.to_lowercase().as_str(), "1" | "true"));
+
+    let mut http = reqwest::Client::builder()
+        .user_agent("BlogDrown-Webhooks")
+        .timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none());
+
+    // development allows webhooks to local listeners
+    if production {
+        http = http.dns_resolver(Arc::new(ip::PublicOnly));
+    }
+
+    let cors_origins = env::var("BLOGDROWN_CORS_ORIGINS")
+        .ok()
+        .map(|origins| {
+            origins
+                .split(',')
+                .map(str::trim)
+                .filter(|o| !o.is_empty())
+                .map(|o| {
+                    o.parse::<HeaderValue>()
+                        .map_err(|_| format!("Invalid origin in BLOGDROWN_CORS_ORIGINS: {o}"))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+
     let state = BlogDrownState {
         prisma: Arc::new(client),
         jwt_secret: Hmac::new_from_slice(
@@ -85,12 +153,81 @@ async fn main() -> Result<(), Box<dyn error::Error>> {
                 .map_err(|_| "missing SECRET_KEY")?
                 .as_bytes(),
         )?,
-        production: env::var("BLOGDROWN_DEV")
-            .map_or(true, |s| !matches!(s.to_lowercase().as_str(), "1" | "true")),
+        production,
         sim_latency: env::var("BLOGDROWN_LATENCY")
             .ok()
             .and_then(|s| s.parse().ok())
             .map(Duration::from_millis),
+        trust_proxy: env::var("BLOGDROWN_TRUST_PROXY")
+            .is_ok_and(|s| matches!(s.to_lowercase().as_str(), "1" | "true")),
+        limits: Arc::default(),
+        deletion_grace: match env::var("BLOGDROWN_DELETION_GRACE_DAYS") {
+            Ok(days) => days
+                .parse::<u32>()
+                .ok()
+                .filter(|d| *d <= MAX_DELETION_GRACE_DAYS)
+                .and_then(|d| TimeDelta::try_days(d.into()))
+                .ok_or_else(|| {
+                    format!(
+                        "BLOGDROWN_DELETION_GRACE_DAYS must be a number of days up to {MAX_DELETION_GRACE_DAYS}, got {days:?}"
+                    )
+                })?,
+            Err(_) => TimeDelta::zero(),
+        },
+        registration: env::var("BLOGDROWN_REGISTRATION")
+            .ok()
+            .map(|s| s.parse())
+            .transpose()?
+            .unwrap_or_default(),
+        invite_quota: env::var("BLOGDROWN_INVITE_QUOTA")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(5),
+        reactions: Arc::new(
+            env::var("BLOGDROWN_REACTIONS")
+                .unwrap_or_else(|_| "👍,❤️,😂,🎉,😮,😢".to_owned())
+                .split(',')
+                .map(str::trim)
+                .filter(|e| !e.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        ),
+        live: live::Hub::default(),
+        http: http.build()?,
+        mailer: match env::var("BLOGDROWN_SMTP_URL") {
+            Ok(url) => {
+                let from = env::var("BLOGDROWN_MAIL_FROM")
+                    .map_err(|_| "BLOGDROWN_MAIL_FROM is required with BLOGDROWN_SMTP_URL")?;
+
+                Some(Arc::new(mail::Mailer::new(&url, &from).map_err(|e| {
+                    format!("Invalid BLOGDROWN_SMTP_URL or BLOGDROWN_MAIL_FROM: {e}")
+                })?))
+            }
+            Err(_) => None,
+        },
+        cors_origins: Arc::new(cors_origins.clone().unwrap_or_default()),
+        storage: Arc::new(
+            storage::LocalStorage::new(
+                env::var("BLOGDROWN_MEDIA_DIR").unwrap_or_else(|_| "media".to_owned()),
+            )
+            .await
+            .map_err(|e| format!("Failed to create media directory: {e}"))?,
+        ),
+        upload_limit: env::var("BLOGDROWN_UPLOAD_LIMIT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(10 * 1024 * 1024),
+        upload_quota: env::var("BLOGDROWN_UPLOAD_QUOTA")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(100 * 1024 * 1024),
+        image_permits: Arc::new(Semaphore::new(
+            std::thread::available_parallelism().map_or(1, |n| n.get()),
+        )),
+        excerpt_length: env::var("BLOGDROWN_EXCERPT_LENGTH")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(200),
     };
 
     if !state.production {
@@ -106,6 +243,15 @@ async fn main() -> Result<(), Box<dyn error::Error>> {
         tracing::info!("running in production mode");
     }
 
+    tracing::info!("registration mode: {:?}", state.registration);
+
+    if state.mailer.is_none() {
+        tracing::warn!("BLOGDROWN_SMTP_URL is not set, email changes are disabled");
+    }
+
+    tokio::spawn(api::purge_scheduled_deletions(state.clone()));
+    tokio::spawn(api::prune_deliveries(state.clone()));
+
     let port = env::var("PORT")
         .ok()
         .and_then(|s| s.parse().ok())
@@ -118,20 +264,39 @@ async fn main() -> Result<(), Box<dyn error::Error>> {
         .await
         .map_err(|_| format!("Failed to bind port {port}"))?;
 
+    let cors = CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            HeaderName::from_static(auth::csrf::CSRF_HEADER),
+        ]);
+
+    let cors = match cors_origins {
+        Some(origins) => {
+            tracing::info!("allowing cross origin requests from {origins:?}");
+
+            cors.allow_origin(origins).allow_credentials(true)
+        }
+        None if !state.production => cors.allow_origin(Any),
+        None => cors,
+    };
+
     let routes = Router::new()
         .nest("/api", api::api_routes())
+        .nest("/media", api::media_routes())
         .fallback_service(serve_frontend())
         .with_state(state.clone())
-        .layer(
-            CorsLayer::new()
-                .allow_methods([Method::GET, Method::POST, Method::PUT])
-                .allow_origin(Any),
-        )
+        .layer(cors)
         .layer(TraceLayer::new_for_http())
         .layer(axum::middleware::from_fn(response_logger))
         .layer(axum::middleware::from_fn_with_state(state, sim_latency));
 
-    axum::serve(listener, routes).await?;
+    axum::serve(
+        listener,
+        routes.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
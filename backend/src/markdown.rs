@@ -0,0 +1,419 @@
+use std::{borrow::Cow, collections::HashSet, sync::LazyLock};
+
+use ammonia::{Builder, UrlRelative};
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd};
+
+use crate::highlight::{self, CLASS_PREFIX};
+
+/// Bumped whenever rendering or summarizing changes, cached html and excerpts of another renderer are made again
+pub const RENDERER: i32 = 3;
+
+/// Reading speed reading times are estimated with
+const WORDS_PER_MINUTE: usize = 200;
+
+const TAGS: [&str; 32] = [
+    "a",
+    "abbr",
+    "blockquote",
+    "br",
+    "code",
+    "del",
+    "em",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "img",
+    "input",
+    "kbd",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "span",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+/// Classes survive sanitizing only on these tags and with these prefixes
+const CLASSES: [(&str, &[&str]); 2] = [
+    ("code", &["language-", CLASS_PREFIX]),
+    ("span", &[CLASS_PREFIX]),
+];
+
+/// Drops classes we did not allow, other attributes were already checked against the allowlist
+fn filter_classes<'u>(tag: &str, attribute: &str, value: &'u str) -> Option<Cow<'u, str>> {
+    if attribute != "class" {
+        return Some(Cow::Borrowed(value));
+    }
+
+    let prefixes = CLASSES.iter().find(|(t, _)| *t == tag)?.1;
+
+    let classes = value
+        .split_ascii_whitespace()
+        .filter(|class| prefixes.iter().any(|prefix| class.starts_with(prefix)))
+        .collect::<Vec<_>>();
+
+    (!classes.is_empty()).then(|| Cow::Owned(classes.join(" ")))
+}
+
+static SANITIZER: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::empty();
+
+    builder
+        .add_tags(TAGS)
+        .add_generic_attributes(["title"])
+        .add_tag_attributes("a", ["href"])
+        .add_tag_attributes("img", ["src", "alt", "width", "height"])
+        .add_tag_attributes("ol", ["start"])
+        .add_tag_attributes("input", ["checked"])
+        // task list items are the only inputs markdown produces
+        .set_tag_attribute_value("input", "type", "checkbox")
+        .set_tag_attribute_value("input", "disabled", "")
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        // relative urls point at our own pages and uploads under /media
+        .url_relative(UrlRelative::PassThrough)
+        .link_rel(Some("noopener noreferrer nofollow ugc"))
+        .strip_comments(true);
+
+    for (tag, _) in CLASSES {
+        builder.add_tag_attributes(tag, ["class"]);
+    }
+
+    builder.attribute_filter(filter_classes);
+
+    builder
+});
+
+pub fn options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS
+}
+
+/// Replaces fenced code blocks in a language we know with their highlighted html
+fn highlight_code<'a>(events: impl Iterator<Item = Event<'a>>) -> impl Iterator<Item = Event<'a>> {
+    // info string and text of the code block being collected
+    let mut block: Option<(CowStr<'a>, String)> = None;
+
+    events.flat_map(move |event| {
+        let Some((_, code)) = &mut block else {
+            return match event {
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info)))
+                    if highlight::supports(highlight::language(&info)) =>
+                {
+                    block = Some((info, String::new()));
+                    vec![]
+                }
+                event => vec![event],
+            };
+        };
+
+        match event {
+            Event::Text(text) => {
+                code.push_str(&text);
+                vec![]
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                let (info, code) = block.take().expect("inside a code block");
+
+                match highlight::highlight(highlight::language(&info), &code) {
+                    Some(html) => vec![Event::Html(html.into())],
+                    // left as a plain code block
+                    None => vec![
+                        Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))),
+                        Event::Text(code.into()),
+                        Event::End(TagEnd::CodeBlock),
+                    ],
+                }
+            }
+            // code blocks only ever contain text
+            _ => vec![],
+        }
+    })
+}
+
+/// Renders markdown to html that is safe to embed in any page.
+///
+/// Raw html in the markdown is kept only where it is on the allowlist, scripts, styles,
+/// event handlers and links with schemes other than http, https and mailto are removed.
+/// Fenced code blocks are highlighted into spans with classes for the stylesheets of [`highlight`].
+pub fn render(markdown: &str) -> String {
+    let mut unsafe_html = String::with_capacity(markdown.len() * 3 / 2);
+
+    html::push_html(
+        &mut unsafe_html,
+        highlight_code(Parser::new_ext(markdown, options())),
+    );
+
+    SANITIZER.clean(&unsafe_html).to_string()
+}
+
+pub struct Summary {
+    pub excerpt: String,
+    pub word_count: usize,
+    pub reading_time_minutes: usize,
+}
+
+impl Summary {
+    pub fn new(excerpt: String, word_count: usize) -> Self {
+        Self {
+            excerpt,
+            word_count,
+            reading_time_minutes: word_count.div_ceil(WORDS_PER_MINUTE).max(1),
+        }
+    }
+}
+
+/// The start of the prose, cut after the last sentence or otherwise the last word that fits.
+///
+/// Sentences are only preferred while they keep at least half of the excerpt.
+fn excerpt(prose: &str, max_chars: usize) -> String {
+    let mut excerpt = String::new();
+    let mut chars = 0;
+    // bytes and chars up to the end of the last complete sentence
+    let mut sentence = None;
+
+    for word in prose.split_whitespace() {
+        let space = usize::from(!excerpt.is_empty());
+        let len = word.chars().count();
+
+        if chars + space + len > max_chars {
+            if let Some((bytes, _)) = sentence.filter(|(_, chars)| chars * 2 >= max_chars) {
+                excerpt.truncate(bytes);
+                return excerpt;
+            }
+
+            if excerpt.is_empty() {
+                excerpt.extend(word.chars().take(max_chars));
+            }
+
+            excerpt.push('…');
+            return excerpt;
+        }
+
+        if space == 1 {
+            excerpt.push(' ');
+        }
+
+        excerpt.push_str(word);
+        chars += space + len;
+
+        if word.ends_with(['.', '!', '?']) {
+            sentence = Some((excerpt.len(), chars));
+        }
+    }
+
+    excerpt
+}
+
+/// Excerpt, word count and reading time from the text the markdown renders to.
+///
+/// All visible text is counted, the excerpt leaves out headings and code blocks
+/// as these rarely read well out of context.
+pub fn summarize(markdown: &str, excerpt_length: usize) -> Summary {
+    let mut text = String::new();
+    let mut prose = String::new();
+    // depth of headings and code blocks, which are left out of the excerpt
+    let mut hidden = 0;
+
+    for event in Parser::new_ext(markdown, options()) {
+        match event {
+            Event::Start(Tag::Heading { .. } | Tag::CodeBlock(_)) => hidden += 1,
+            Event::Text(t) | Event::Code(t) => {
+                text.push_str(&t);
+
+                if hidden == 0 {
+                    prose.push_str(&t);
+                }
+            }
+            // inline elements continue the word they are in, `**bo**ld` is one word
+            Event::End(
+                TagEnd::Emphasis
+                | TagEnd::Strong
+                | TagEnd::Strikethrough
+                | TagEnd::Link
+                | TagEnd::Image,
+            ) => {}
+            Event::End(end) => {
+                if matches!(end, TagEnd::Heading(_) | TagEnd::CodeBlock) {
+                    hidden -= 1;
+                }
+
+                text.push(' ');
+                prose.push(' ');
+            }
+            Event::SoftBreak | Event::HardBreak | Event::Rule => {
+                text.push(' ');
+                prose.push(' ');
+            }
+            _ => {}
+        }
+    }
+
+    Summary::new(
+        excerpt(&prose, excerpt_length),
+        text.split_whitespace().count(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_scripts() {
+        let html = render("<script>alert(1)</script>\n\n<p>after</p>");
+
+        assert!(!html.contains("script"), "{html}");
+        assert!(html.contains("<p>after</p>"), "{html}");
+    }
+
+    #[test]
+    fn removes_script_links() {
+        for markdown in [
+            "[click](javascript:alert(1))",
+            "<a href=\"javascript:alert(1)\">click</a>",
+            "<a href=\"JaVaScRiPt:alert(1)\">click</a>",
+            "![x](data:image/svg+xml;base64,PHN2Zz48L3N2Zz4=)",
+        ] {
+            let html = render(markdown);
+            assert!(!html.to_lowercase().contains("javascript:"), "{html}");
+            assert!(!html.contains("data:"), "{html}");
+        }
+    }
+
+    #[test]
+    fn removes_event_handlers() {
+        let html = render(
+            "<img src=\"/media/a.png\" onerror=\"alert(1)\">\n\n<p onclick=\"alert(1)\">x</p>",
+        );
+
+        assert!(!html.contains("onerror"), "{html}");
+        assert!(!html.contains("onclick"), "{html}");
+        assert!(html.contains("src=\"/media/a.png\""), "{html}");
+    }
+
+    #[test]
+    fn marks_links() {
+        let html = render("[site](https://example.com)");
+
+        assert!(
+            html.contains("rel=\"noopener noreferrer nofollow ugc\""),
+            "{html}"
+        );
+    }
+
+    #[test]
+    fn filters_classes() {
+        assert_eq!(
+            filter_classes("span", "class", "hl-keyword evil hl-rust"),
+            Some(Cow::Borrowed("hl-keyword hl-rust"))
+        );
+        assert_eq!(
+            filter_classes("code", "class", "language-rust other"),
+            Some(Cow::Borrowed("language-rust"))
+        );
+        assert_eq!(filter_classes("span", "class", "language-rust"), None);
+        assert_eq!(filter_classes("p", "class", "hl-keyword"), None);
+        assert_eq!(
+            filter_classes("a", "href", "/media/a.png"),
+            Some(Cow::Borrowed("/media/a.png"))
+        );
+    }
+
+    #[test]
+    fn removes_foreign_classes() {
+        let html = render("<p class=\"hl-x admin\">x <span class=\"hidden\">y</span></p>");
+
+        assert!(!html.contains("class"), "{html}");
+    }
+
+    #[test]
+    fn highlights_known_languages() {
+        let html = render("```rust,ignore\nfn main() {}\n```");
+
+        assert!(
+            html.contains("<code class=\"language-rust hl-code\">"),
+            "{html}"
+        );
+        assert!(html.contains("<span class=\"hl-"), "{html}");
+    }
+
+    #[test]
+    fn leaves_other_code_plain() {
+        let html = render("```nosuchlanguage\na < b\n```\n\n```\nplain\n```");
+
+        assert!(
+            html.contains("<code class=\"language-nosuchlanguage\">a &lt; b"),
+            "{html}"
+        );
+        assert!(html.contains("<pre><code>plain"), "{html}");
+        assert!(!html.contains("hl-"), "{html}");
+    }
+
+    #[test]
+    fn escapes_highlighted_code() {
+        let html = render("```html\n<script>alert(1)</script>\n```");
+
+        assert!(!html.contains("<script"), "{html}");
+        assert!(html.contains("&lt;"), "{html}");
+    }
+
+    #[test]
+    fn keeps_short_prose() {
+        assert_eq!(excerpt("Hello   world.", 200), "Hello world.");
+        assert_eq!(excerpt("", 200), "");
+    }
+
+    #[test]
+    fn cuts_after_sentence() {
+        assert_eq!(
+            excerpt("First sentence here. Second sentence goes on and on", 30),
+            "First sentence here."
+        );
+    }
+
+    #[test]
+    fn cuts_after_word_when_sentence_is_short() {
+        assert_eq!(
+            excerpt("Hi. this is a longer excerpt text", 20),
+            "Hi. this is a longer…"
+        );
+    }
+
+    #[test]
+    fn cuts_long_words() {
+        assert_eq!(excerpt("abcdefghij klm", 4), "abcd…");
+        assert_eq!(excerpt("héllo wörld", 7), "héllo…");
+    }
+
+    #[test]
+    fn summarizes_visible_text() {
+        let summary = summarize(
+            "# Title\n\nBody **bo**ld text.\n\n```\nsome code\n```\n",
+            200,
+        );
+
+        assert_eq!(summary.excerpt, "Body bold text.");
+        assert_eq!(summary.word_count, 6);
+        assert_eq!(summary.reading_time_minutes, 1);
+    }
+
+    #[test]
+    fn estimates_reading_time() {
+        let summary = summarize(&"word ".repeat(WORDS_PER_MINUTE + 1), 10);
+
+        assert_eq!(summary.word_count, WORDS_PER_MINUTE + 1);
+        assert_eq!(summary.reading_time_minutes, 2);
+    }
+}
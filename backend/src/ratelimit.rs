@@ -0,0 +1,255 @@
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header::RETRY_AFTER, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::{
+    api::{ApiError, Error},
+    ip::ClientIp,
+    BlogDrownState,
+};
+
+/// Entries are pruned once the table grows past this many keys
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    /// Attempts allowed within `window` before backoff kicks in
+    pub free_attempts: u32,
+    /// Attempts older than this are forgotten
+    pub window: Duration,
+    /// First backoff delay, doubled for every further attempt
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Attempts after which the key is locked out entirely
+    pub lockout_after: u32,
+    pub lockout: Duration,
+}
+
+#[derive(Debug)]
+struct Attempts {
+    count: u32,
+    last: Instant,
+    blocked_until: Option<Instant>,
+}
+
+/// Keyed attempt counter with exponential backoff and temporary lockout
+#[derive(Debug)]
+pub struct RateLimiter {
+    policy: Policy,
+    attempts: Mutex<HashMap<String, Attempts>>,
+}
+
+impl RateLimiter {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            attempts: Mutex::default(),
+        }
+    }
+
+    /// Checks whether `key` is currently blocked, without recording an attempt
+    pub fn check(&self, key: &str) -> Result<(), TooManyRequests> {
+        let now = Instant::now();
+        let attempts = self.attempts.lock().expect("ratelimit mutex poisoned");
+
+        match attempts.get(key).and_then(|a| a.blocked_until) {
+            Some(until) if until > now => Err(TooManyRequests(until - now)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks `key` and counts this as an attempt
+    pub fn hit(&self, key: &str) -> Result<(), TooManyRequests> {
+        self.check(key)?;
+        self.failure(key);
+
+        Ok(())
+    }
+
+    /// Records a failed attempt, blocking the key if it is over budget
+    pub fn failure(&self, key: &str) {
+        let now = Instant::now();
+        let policy = &self.policy;
+        let mut attempts = self.attempts.lock().expect("ratelimit mutex poisoned");
+
+        if attempts.len() > PRUNE_THRESHOLD {
+            attempts.retain(|_, a| {
+                a.blocked_until.is_some_and(|until| until > now)
+                    || now.duration_since(a.last) < policy.window
+            });
+        }
+
+        let entry = attempts.entry(key.to_owned()).or_insert(Attempts {
+            count: 0,
+            last: now,
+            blocked_until: None,
+        });
+
+        if now.duration_since(entry.last) >= policy.window {
+            entry.count = 0;
+        }
+
+        entry.count += 1;
+        entry.last = now;
+
+        if entry.count >= policy.lockout_after {
+            tracing::warn!(
+                "rate limit: locking out {key:?} after {} attempts",
+                entry.count
+            );
+            entry.blocked_until = Some(now + policy.lockout);
+        } else if let Some(over) = entry.count.checked_sub(policy.free_attempts + 1) {
+            let backoff = policy
+                .base_backoff
+                .saturating_mul(2u32.saturating_pow(over))
+                .min(policy.max_backoff);
+
+            entry.blocked_until = Some(now + backoff);
+        }
+    }
+
+    /// Forgets all attempts for `key`
+    pub fn success(&self, key: &str) {
+        self.attempts
+            .lock()
+            .expect("ratelimit mutex poisoned")
+            .remove(key);
+    }
+}
+
+#[derive(Debug)]
+pub struct Limits {
+    /// Every login and signup attempt, keyed by client address
+    pub auth_ip: RateLimiter,
+    /// Failed logins, keyed by account email
+    pub login_account: RateLimiter,
+    /// Every upload, keyed by client address, as each may take a core for a while
+    pub upload_ip: RateLimiter,
+    /// Webhook test pings, keyed by client address, as each may wait for the delivery timeout
+    pub webhook_test_ip: RateLimiter,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            auth_ip: RateLimiter::new(Policy {
+                free_attempts: 30,
+                window: Duration::from_secs(5 * 60),
+                base_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(60),
+                lockout_after: 100,
+                lockout: Duration::from_secs(15 * 60),
+            }),
+            login_account: RateLimiter::new(Policy {
+                free_attempts: 5,
+                window: Duration::from_secs(15 * 60),
+                base_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(5 * 60),
+                lockout_after: 20,
+                lockout: Duration::from_secs(30 * 60),
+            }),
+            upload_ip: RateLimiter::new(Policy {
+                free_attempts: 30,
+                window: Duration::from_secs(10 * 60),
+                base_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(60),
+                lockout_after: 200,
+                lockout: Duration::from_secs(15 * 60),
+            }),
+            webhook_test_ip: RateLimiter::new(Policy {
+                free_attempts: 10,
+                window: Duration::from_secs(5 * 60),
+                base_backoff: Duration::from_secs(5),
+                max_backoff: Duration::from_secs(60),
+                lockout_after: 50,
+                lockout: Duration::from_secs(15 * 60),
+            }),
+        }
+    }
+}
+
+pub struct TooManyRequests(pub Duration);
+
+impl TooManyRequests {
+    fn retry_secs(&self) -> u64 {
+        // round up so clients never retry early
+        self.0.as_secs() + u64::from(self.0.subsec_nanos() > 0)
+    }
+
+    pub fn error(&self) -> ApiError {
+        let mut err = Error::new("Too many attempts, try again later");
+        err.add("retry_after", self.retry_secs().to_string());
+
+        (StatusCode::TOO_MANY_REQUESTS, Json(err))
+    }
+}
+
+impl IntoResponse for TooManyRequests {
+    fn into_response(self) -> Response {
+        ([(RETRY_AFTER, self.retry_secs().to_string())], self.error()).into_response()
+    }
+}
+
+/// Selects which limiter a [`Throttle`] draws from
+pub trait Bucket: Send + Sync + 'static {
+    fn limiter(limits: &Limits) -> &RateLimiter;
+}
+
+pub struct AuthAttempts;
+
+impl Bucket for AuthAttempts {
+    fn limiter(limits: &Limits) -> &RateLimiter {
+        &limits.auth_ip
+    }
+}
+
+pub struct UploadAttempts;
+
+impl Bucket for UploadAttempts {
+    fn limiter(limits: &Limits) -> &RateLimiter {
+        &limits.upload_ip
+    }
+}
+
+pub struct WebhookTests;
+
+impl Bucket for WebhookTests {
+    fn limiter(limits: &Limits) -> &RateLimiter {
+        &limits.webhook_test_ip
+    }
+}
+
+/// Counts the request against the client address in bucket `B`,
+/// rejecting with 429 once the client is over budget
+pub struct Throttle<B>(PhantomData<B>);
+
+#[async_trait]
+impl<B: Bucket> FromRequestParts<BlogDrownState> for Throttle<B> {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &BlogDrownState,
+    ) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip) = ClientIp::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        B::limiter(&state.limits)
+            .hit(&ip.to_string())
+            .map_err(IntoResponse::into_response)?;
+
+        Ok(Self(PhantomData))
+    }
+}
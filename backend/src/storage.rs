@@ -0,0 +1,81 @@
+use std::{fmt, io, path::PathBuf};
+
+use axum::{async_trait, body::Bytes};
+
+/// Where uploaded files are kept, keys are generated by us but may arrive from a request path
+/// so implementations must reject anything [`valid_key`] does not accept
+#[async_trait]
+pub trait Storage: fmt::Debug + Send + Sync {
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()>;
+
+    /// `None` when nothing is stored under the key
+    async fn get(&self, key: &str) -> io::Result<Option<Bytes>>;
+
+    /// Removing a missing key is not an error
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// A single path segment of ascii letters, digits, `-`, `_` and non-leading dots
+pub fn valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= 128
+        && !key.starts_with('.')
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+/// Keeps files in a directory on the local filesystem
+#[derive(Debug)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub async fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+
+        tokio::fs::create_dir_all(&root).await?;
+
+        Ok(Self { root })
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        if !valid_key(key) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid storage key {key:?}"),
+            ));
+        }
+
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: Bytes) -> io::Result<()> {
+        let path = self.path(key)?;
+
+        // written aside and renamed so readers never see a partial file
+        let partial = self.root.join(format!(".{key}.partial"));
+
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, path).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<Bytes>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data.into())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
@@ -67,10 +67,23 @@ export class ApiError {
 	}
 }
 
+function csrfToken(): string {
+	for (const cookie of document.cookie.split("; ")) {
+		const [name, value] = cookie.split("=");
+
+		if (name === "csrf") {
+			return value;
+		}
+	}
+
+	return "";
+}
+
 async function jpost<T>(route: string, body: object): Promise<T> {
 	const resp = await fetch(`${BASE_URL}${route}`, {
 		headers: {
 			"Content-Type": "application/json",
+			"X-CSRF-Token": csrfToken(),
 		},
 		method: "POST",
 		body: JSON.stringify(body),
@@ -87,6 +100,7 @@ async function jput(route: string, body: object): Promise<Updated> {
 	const resp = await fetch(`${BASE_URL}${route}`, {
 		headers: {
 			"Content-Type": "application/json",
+			"X-CSRF-Token": csrfToken(),
 		},
 		method: "PUT",
 		body: JSON.stringify(body),
@@ -114,6 +128,9 @@ async function datalessfetch<T>(
 	method: "GET" | "DELETE" | "POST" | "PUT",
 ): Promise<T | null> {
 	const resp = await fetch(`${BASE_URL}${route}`, {
+		headers: {
+			"X-CSRF-Token": csrfToken(),
+		},
 		method,
 	});
 